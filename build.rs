@@ -1,29 +1,33 @@
-// Build the VM's protobuf into a Rust server
+// Build the VM's protobuf into Rust servers and clients
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // The servers we'll expose
+    // The servers we'll expose.
+    // Their clients are used by the mock avalanchego host in the integration tests.
     tonic_build::configure()
-        .build_client(false)
         .format(true)
         .compile(&["proto/vm.proto", "proto/ghttp.proto"], &["proto"])?;
 
     // the clients we'll consume
+    // Their servers are provided by the mock avalanchego host in the integration tests.
+    tonic_build::configure().format(true).compile(
+        &[
+            "proto/appsender.proto",
+            "proto/galiasreader.proto",
+            "proto/gkeystore.proto",
+            "proto/gsharedmemory.proto",
+            "proto/gsubnetlookup.proto",
+            "proto/messenger.proto",
+            "proto/rpcdb.proto",
+            "proto/greadcloser.proto",
+            "proto/gresponsewriter.proto",
+        ],
+        &["proto"],
+    )?;
+
+    // go-plugin's broker, which grr-plugin serves, and the mock host dials.
     tonic_build::configure()
         .build_server(false)
         .format(true)
-        .compile(
-            &[
-                "proto/appsender.proto",
-                "proto/galiasreader.proto",
-                "proto/gkeystore.proto",
-                "proto/gsharedmemory.proto",
-                "proto/gsubnetlookup.proto",
-                "proto/messenger.proto",
-                "proto/rpcdb.proto",
-                "proto/greadcloser.proto",
-                "proto/gresponsewriter.proto",
-            ],
-            &["proto"],
-        )?;
+        .compile(&["proto/grpc_broker.proto"], &["proto"])?;
 
     Ok(())
 }
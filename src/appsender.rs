@@ -1 +1,498 @@
+// Typed request/response messages between VMs on different nodes, over avalanchego's
+// AppRequest, AppResponse and AppRequestFailed.
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/common/appsender/app_sender.go
+//
+// Every message is serialized as:
+//     [codec version: u16] [message type: u16] [message fields, as AppMessage packs them]
+//
+// avalanchego guarantees that every request sent gets either exactly one AppResponse,
+// or an AppRequestFailed (e.g. once the request times out), so every response
+// awaited through AppNetwork eventually resolves.
+use super::codec::{Packer, Unpacker, CODEC_VERSION};
+use super::error::LandslideError;
+use super::proto::appsender::app_sender_client::AppSenderClient;
+use super::proto::appsender::{SendAppRequestMsg, SendAppResponseMsg};
+use anyhow::anyhow;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::oneshot;
+use tonic::transport::Channel;
 
+// How long a response to a request of our own is awaited. SendAppRequestMsg carries no
+// deadline: avalanchego gives each request its own, of at most 10 seconds by default, and
+// fails it with AppRequestFailed once that passes. Waiting as long as that covers every
+// response that can still come, without waiting forever on a failure that never arrives.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub trait AppMessage: Sized + Send + 'static {
+    // Identifies this message on the wire. Unique among the messages registered with an AppNetwork.
+    const MESSAGE_TYPE: u16;
+
+    fn pack(&self, packer: &mut Packer) -> Result<(), LandslideError>;
+
+    fn unpack(unpacker: &mut Unpacker) -> Result<Self, LandslideError>;
+}
+
+// A message sent with AppRequest, answered by a Response sent with AppResponse
+pub trait AppRequest: AppMessage {
+    type Response: AppMessage;
+}
+
+pub fn encode<M: AppMessage>(message: &M) -> Result<Vec<u8>, LandslideError> {
+    let mut packer = Packer::with_version(CODEC_VERSION)?;
+    packer.pack_short(M::MESSAGE_TYPE)?;
+    message.pack(&mut packer)?;
+    Ok(packer.into_bytes())
+}
+
+pub fn decode<M: AppMessage>(bytes: &[u8]) -> Result<M, LandslideError> {
+    let mut unpacker = Unpacker::with_version(bytes, CODEC_VERSION)?;
+    let message_type = unpacker.unpack_short()?;
+    if message_type != M::MESSAGE_TYPE {
+        return Err(LandslideError::Codec(anyhow!(
+            "Expected a message of type {}, but got one of type {}",
+            M::MESSAGE_TYPE,
+            message_type
+        )));
+    }
+
+    let message = M::unpack(&mut unpacker)?;
+    unpacker.done()?;
+    Ok(message)
+}
+
+fn message_type(bytes: &[u8]) -> Result<u16, LandslideError> {
+    let mut unpacker = Unpacker::with_version(bytes, CODEC_VERSION)?;
+    unpacker.unpack_short()
+}
+
+// A request received from a peer, to be answered with AppNetwork::respond
+#[derive(Debug)]
+pub struct IncomingRequest {
+    pub node_id: Vec<u8>,
+    pub request_id: u32,
+    pub deadline: OffsetDateTime,
+    pub message_type: u16,
+    bytes: Vec<u8>,
+}
+
+impl IncomingRequest {
+    pub fn is<R: AppRequest>(&self) -> bool {
+        self.message_type == R::MESSAGE_TYPE
+    }
+
+    pub fn decode<R: AppRequest>(&self) -> Result<R, LandslideError> {
+        decode(&self.bytes)
+    }
+}
+
+// The response to a request sent with AppNetwork::request.
+// Await it only after releasing any lock the VM is behind, since the response
+// is delivered through the VM (in app_response or app_request_failed).
+#[derive(Debug)]
+pub struct PendingResponse<M: AppMessage> {
+    pub request_id: u32,
+    receiver: oneshot::Receiver<Result<Vec<u8>, LandslideError>>,
+    message: PhantomData<M>,
+}
+
+impl<M: AppMessage> PendingResponse<M> {
+    pub async fn recv(self) -> Result<M, LandslideError> {
+        let bytes = self.receiver.await.map_err(|_| {
+            LandslideError::Other(anyhow!(
+                "Request {} was dropped before it was answered",
+                self.request_id
+            ))
+        })??;
+        decode(&bytes)
+    }
+}
+
+#[derive(Debug)]
+struct OutstandingRequest {
+    node_id: Vec<u8>,
+    response_type: u16,
+    sender: oneshot::Sender<Result<Vec<u8>, LandslideError>>,
+}
+
+// Tracks requests in both directions: requests sent to peers until they are answered
+// or fail, and requests received from peers until they are answered or their deadline passes.
+#[derive(Debug, Default)]
+pub struct AppNetwork {
+    // request message type -> response message type
+    registered: HashMap<u16, u16>,
+    registered_types: HashSet<u16>,
+
+    next_request_id: u32,
+    outstanding: HashMap<u32, OutstandingRequest>,
+    incoming: HashMap<(Vec<u8>, u32), OffsetDateTime>,
+}
+
+impl AppNetwork {
+    pub fn new() -> AppNetwork {
+        AppNetwork::default()
+    }
+
+    // Only registered requests are sent or accepted. Requests from peers of any other type are dropped.
+    pub fn register<R: AppRequest>(&mut self) -> Result<(), LandslideError> {
+        for message_type in [R::MESSAGE_TYPE, R::Response::MESSAGE_TYPE] {
+            if !self.registered_types.insert(message_type) {
+                return Err(LandslideError::Other(anyhow!(
+                    "App message type {} is already registered",
+                    message_type
+                )));
+            }
+        }
+
+        self.registered
+            .insert(R::MESSAGE_TYPE, R::Response::MESSAGE_TYPE);
+        Ok(())
+    }
+
+    fn check_registered<R: AppRequest>(&self) -> Result<(), LandslideError> {
+        match self.registered.get(&R::MESSAGE_TYPE) {
+            Some(response_type) if *response_type == R::Response::MESSAGE_TYPE => Ok(()),
+            _ => Err(LandslideError::UnknownAppMessageType {
+                message_type: R::MESSAGE_TYPE,
+            }),
+        }
+    }
+
+    // Prepares a request to node_id, returning the message to send and the response to await
+    pub fn request<R: AppRequest>(
+        &mut self,
+        node_id: &[u8],
+        request: &R,
+    ) -> Result<(SendAppRequestMsg, PendingResponse<R::Response>), LandslideError> {
+        self.check_registered::<R>()?;
+
+        let request_id = self.next_request_id;
+        self.next_request_id = self.next_request_id.wrapping_add(1);
+
+        let (sender, receiver) = oneshot::channel();
+        self.outstanding.insert(
+            request_id,
+            OutstandingRequest {
+                node_id: Vec::from(node_id),
+                response_type: R::Response::MESSAGE_TYPE,
+                sender,
+            },
+        );
+
+        Ok((
+            SendAppRequestMsg {
+                node_i_ds: vec![Vec::from(node_id)],
+                request_id,
+                request: encode(request)?,
+            },
+            PendingResponse {
+                request_id,
+                receiver,
+                message: PhantomData,
+            },
+        ))
+    }
+
+    pub async fn send_request<R: AppRequest>(
+        &mut self,
+        app_sender: &mut AppSenderClient<Channel>,
+        node_id: &[u8],
+        request: &R,
+    ) -> Result<PendingResponse<R::Response>, LandslideError> {
+        let (msg, pending) = self.request(node_id, request)?;
+        if let Err(status) = app_sender.send_app_request(msg).await {
+            self.outstanding.remove(&pending.request_id);
+            return Err(status.into());
+        }
+
+        Ok(pending)
+    }
+
+    // Delivers a response to whoever awaits it. Responses nobody is waiting for
+    // (or that come from a node the request wasn't sent to) are dropped.
+    pub fn on_response(&mut self, node_id: &[u8], request_id: u32, bytes: Vec<u8>) {
+        let outstanding = match self.outstanding.remove(&request_id) {
+            Some(outstanding) if outstanding.node_id == node_id => outstanding,
+            Some(outstanding) => {
+                log::debug!(
+                    "Dropping response to request {} from node {}, which it wasn't sent to",
+                    request_id,
+                    hex::encode(node_id)
+                );
+                self.outstanding.insert(request_id, outstanding);
+                return;
+            }
+            None => {
+                log::debug!("Dropping response to unknown request {}", request_id);
+                return;
+            }
+        };
+
+        let result = match message_type(&bytes) {
+            Ok(message_type) if message_type == outstanding.response_type => Ok(bytes),
+            Ok(message_type) => Err(LandslideError::UnknownAppMessageType { message_type }),
+            Err(err) => Err(err),
+        };
+        // the receiver is gone if nobody is waiting any more
+        let _ = outstanding.sender.send(result);
+    }
+
+    // Fails a request whose response will never come. As with responses, only
+    // the node the request was sent to can fail it.
+    pub fn on_request_failed(&mut self, node_id: &[u8], request_id: u32) {
+        match self.outstanding.remove(&request_id) {
+            Some(outstanding) if outstanding.node_id == node_id => {
+                let _ = outstanding
+                    .sender
+                    .send(Err(LandslideError::AppRequestFailed {
+                        node_id: hex::encode(node_id),
+                        request_id,
+                    }));
+            }
+            Some(outstanding) => {
+                log::debug!(
+                    "Dropping failure of request {} from node {}, which it wasn't sent to",
+                    request_id,
+                    hex::encode(node_id)
+                );
+                self.outstanding.insert(request_id, outstanding);
+            }
+            None => log::debug!("Dropping failure of unknown request {}", request_id),
+        }
+    }
+
+    pub fn is_outstanding(&self, request_id: u32) -> bool {
+        self.outstanding.contains_key(&request_id)
+    }
+
+    // Fails every request still waiting for a response, and forgets requests
+    // from peers, e.g. when the VM shuts down
+    pub fn close(&mut self) {
+        for (request_id, outstanding) in self.outstanding.drain() {
+            let _ = outstanding
+                .sender
+                .send(Err(LandslideError::AppRequestFailed {
+                    node_id: hex::encode(&outstanding.node_id),
+                    request_id,
+                }));
+        }
+        self.incoming.clear();
+    }
+
+    // Accepts a request from a peer, to be answered before its deadline.
+    // Requests past their deadline, or of an unregistered type, are dropped (None).
+    pub fn receive_request(
+        &mut self,
+        node_id: &[u8],
+        request_id: u32,
+        deadline: OffsetDateTime,
+        bytes: &[u8],
+    ) -> Result<Option<IncomingRequest>, LandslideError> {
+        let now = OffsetDateTime::now_utc();
+        self.incoming.retain(|_, deadline| *deadline > now);
+
+        if deadline <= now {
+            log::debug!(
+                "Dropping request {} from node {}, since its deadline {} has passed",
+                request_id,
+                hex::encode(node_id),
+                deadline
+            );
+            return Ok(None);
+        }
+
+        let message_type = message_type(bytes)?;
+        if !self.registered.contains_key(&message_type) {
+            log::debug!(
+                "Dropping request {} from node {} of unknown type {}",
+                request_id,
+                hex::encode(node_id),
+                message_type
+            );
+            return Ok(None);
+        }
+
+        self.incoming
+            .insert((Vec::from(node_id), request_id), deadline);
+
+        Ok(Some(IncomingRequest {
+            node_id: Vec::from(node_id),
+            request_id,
+            deadline,
+            message_type,
+            bytes: Vec::from(bytes),
+        }))
+    }
+
+    // Prepares the response to a request received from a peer. Each request is
+    // answered at most once, and not at all once its deadline has passed (None).
+    pub fn respond<R: AppRequest>(
+        &mut self,
+        request: &IncomingRequest,
+        response: &R::Response,
+    ) -> Result<Option<SendAppResponseMsg>, LandslideError> {
+        self.check_registered::<R>()?;
+        if !request.is::<R>() {
+            return Err(LandslideError::Other(anyhow!(
+                "Request {} of type {} can't be answered as a request of type {}",
+                request.request_id,
+                request.message_type,
+                R::MESSAGE_TYPE
+            )));
+        }
+
+        let key = (request.node_id.clone(), request.request_id);
+        match self.incoming.remove(&key) {
+            Some(deadline) if deadline > OffsetDateTime::now_utc() => {}
+            _ => {
+                log::debug!(
+                    "Not answering request {} from node {}: already answered, or past its deadline",
+                    request.request_id,
+                    hex::encode(&request.node_id)
+                );
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(SendAppResponseMsg {
+            node_id: request.node_id.clone(),
+            request_id: request.request_id,
+            response: encode(response)?,
+        }))
+    }
+
+    pub async fn send_response<R: AppRequest>(
+        &mut self,
+        app_sender: &mut AppSenderClient<Channel>,
+        request: &IncomingRequest,
+        response: &R::Response,
+    ) -> Result<(), LandslideError> {
+        if let Some(msg) = self.respond::<R>(request, response)? {
+            app_sender.send_app_response(msg).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    struct Ping(u64);
+
+    #[derive(Debug, PartialEq)]
+    struct Pong(u64);
+
+    impl AppMessage for Ping {
+        const MESSAGE_TYPE: u16 = 1;
+
+        fn pack(&self, packer: &mut Packer) -> Result<(), LandslideError> {
+            packer.pack_long(self.0)
+        }
+
+        fn unpack(unpacker: &mut Unpacker) -> Result<Self, LandslideError> {
+            Ok(Ping(unpacker.unpack_long()?))
+        }
+    }
+
+    impl AppMessage for Pong {
+        const MESSAGE_TYPE: u16 = 2;
+
+        fn pack(&self, packer: &mut Packer) -> Result<(), LandslideError> {
+            packer.pack_long(self.0)
+        }
+
+        fn unpack(unpacker: &mut Unpacker) -> Result<Self, LandslideError> {
+            Ok(Pong(unpacker.unpack_long()?))
+        }
+    }
+
+    impl AppRequest for Ping {
+        type Response = Pong;
+    }
+
+    fn network() -> AppNetwork {
+        let mut network = AppNetwork::new();
+        network.register::<Ping>().unwrap();
+        network
+    }
+
+    #[tokio::test]
+    async fn test_request_response_between_nodes() {
+        let mut alice = network();
+        let mut bob = network();
+
+        let (msg, pending) = alice.request(b"bob", &Ping(7)).unwrap();
+        assert_eq!(msg.node_i_ds, vec![b"bob".to_vec()]);
+
+        let deadline = OffsetDateTime::now_utc() + Duration::seconds(10);
+        let incoming = bob
+            .receive_request(b"alice", msg.request_id, deadline, &msg.request)
+            .unwrap()
+            .unwrap();
+        assert!(incoming.is::<Ping>());
+        let ping: Ping = incoming.decode().unwrap();
+
+        let response = bob
+            .respond::<Ping>(&incoming, &Pong(ping.0 + 1))
+            .unwrap()
+            .unwrap();
+        // a request is only answered once
+        assert!(bob.respond::<Ping>(&incoming, &Pong(0)).unwrap().is_none());
+
+        alice.on_response(b"bob", response.request_id, response.response);
+        assert_eq!(pending.recv().await.unwrap(), Pong(8));
+    }
+
+    #[tokio::test]
+    async fn test_request_failed() {
+        let mut network = network();
+        let (msg, pending) = network.request(b"bob", &Ping(1)).unwrap();
+
+        // a response from the wrong node doesn't resolve the request
+        network.on_response(b"mallory", msg.request_id, encode(&Pong(1)).unwrap());
+        // ...and neither does a failure from the wrong node
+        network.on_request_failed(b"mallory", msg.request_id);
+        assert!(network.is_outstanding(msg.request_id));
+        network.on_request_failed(b"bob", msg.request_id);
+
+        assert!(matches!(
+            pending.recv().await,
+            Err(LandslideError::AppRequestFailed { request_id: 0, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_and_unknown_requests_are_dropped() {
+        let mut network = network();
+        let past = OffsetDateTime::now_utc() - Duration::seconds(1);
+        let future = OffsetDateTime::now_utc() + Duration::seconds(10);
+
+        let ping = encode(&Ping(1)).unwrap();
+        assert!(network
+            .receive_request(b"alice", 1, past, &ping)
+            .unwrap()
+            .is_none());
+
+        let pong = encode(&Pong(1)).unwrap();
+        assert!(network
+            .receive_request(b"alice", 2, future, &pong)
+            .unwrap()
+            .is_none());
+
+        assert!(network
+            .receive_request(b"alice", 3, future, b"garbage")
+            .is_err());
+    }
+
+    #[test]
+    fn test_register_rejects_clashing_types() {
+        let mut network = network();
+        assert!(network.register::<Ping>().is_err());
+    }
+}
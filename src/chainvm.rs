@@ -0,0 +1,346 @@
+// The traits a custom VM implements to run on avalanchego, without any of the gRPC glue.
+// Modeled on: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/snowman/block/vm.go
+// The rpcchainvm module serves any ChainVm to avalanchego.
+
+use crate::context::Context;
+use crate::error::LandslideError;
+use crate::id::Id;
+use crate::proto::appsender::app_sender_client::AppSenderClient;
+use crate::proto::galiasreader::alias_reader_client::AliasReaderClient;
+use crate::proto::gkeystore::keystore_client::KeystoreClient;
+use crate::proto::gsharedmemory::shared_memory_client::SharedMemoryClient;
+use crate::proto::gsubnetlookup::subnet_lookup_client::SubnetLookupClient;
+use crate::proto::messenger::messenger_client::MessengerClient;
+use crate::proto::rpcdb::database_client::DatabaseClient;
+use crate::timestamp::Timestamp;
+use jsonrpc_core::IoHandler;
+use num_derive::FromPrimitive;
+use prometheus::Registry;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+
+// The size of the length prefix avalanchego puts in front of every container in a message
+// Copied from: https://github.com/ava-labs/avalanchego/blob/master/utils/wrappers/packing.go#L23
+const INT_LEN: usize = 4;
+
+// Copied from: https://github.com/ava-labs/avalanchego/blob/master/snow/choices/status.go
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, FromPrimitive)]
+pub enum Status {
+    Unknown,
+    Processing,
+    Rejected,
+    Accepted,
+}
+
+impl Status {
+    pub fn fetched(&self) -> bool {
+        match self {
+            Self::Processing => true,
+            _ => self.decided(),
+        }
+    }
+
+    pub fn decided(&self) -> bool {
+        matches!(self, Self::Rejected | Self::Accepted)
+    }
+
+    pub fn valid(&self) -> bool {
+        !matches!(self, Self::Unknown)
+    }
+
+    // Whether a block with this status still has to be decided as asked. Repeating
+    // a decision is a no-op, but a decided block never changes its status.
+    pub fn needs_decision(&self, block_id: &Id, decision: Status) -> Result<bool, LandslideError> {
+        match (self, decision) {
+            (Self::Processing, _) => Ok(true),
+            (Self::Accepted, Self::Accepted) | (Self::Rejected, Self::Rejected) => Ok(false),
+            (Self::Accepted | Self::Rejected, _) => Err(LandslideError::BlockAlreadyDecided {
+                block_id: block_id.clone(),
+                status: *self,
+            }),
+            (Self::Unknown, _) => Err(LandslideError::InvalidBlock {
+                block_id: block_id.clone(),
+                reason: "it was never verified".to_string(),
+            }),
+        }
+    }
+}
+
+// Where a VM is in its life, as driven by avalanchego.
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/snow/state.go
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    // Until avalanchego starts bootstrapping the chain
+    Initializing,
+    // Catching up with blocks the network already accepted
+    Bootstrapping,
+    // Following consensus, building and verifying new blocks
+    NormalOp,
+    ShuttingDown,
+}
+
+impl Lifecycle {
+    // Moves on to the next state. avalanchego may bootstrap again after
+    // normal operation, and may shut the VM down at any point.
+    pub fn transition(self, to: Lifecycle) -> Result<Lifecycle, LandslideError> {
+        match (self, to) {
+            (Self::ShuttingDown, _) => Err(LandslideError::LifecycleTransition { from: self, to }),
+            (_, Self::ShuttingDown)
+            | (Self::Initializing, Self::Bootstrapping)
+            | (Self::Bootstrapping, Self::Bootstrapping)
+            | (Self::Bootstrapping, Self::NormalOp)
+            | (Self::NormalOp, Self::Bootstrapping) => Ok(to),
+            _ => Err(LandslideError::LifecycleTransition { from: self, to }),
+        }
+    }
+
+    // Fails unless in normal operation, for what only makes sense then (like building blocks)
+    pub fn ensure_normal_op(&self) -> Result<(), LandslideError> {
+        match self {
+            Self::NormalOp => Ok(()),
+            _ => Err(LandslideError::NotInNormalOp { lifecycle: *self }),
+        }
+    }
+}
+
+// A block as the consensus engine sees it.
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/snow/consensus/snowman/block.go
+// Verify, Accept and Reject live on ChainVm instead, since they change the VM's state.
+pub trait Block: Clone + Send + Sync + 'static {
+    fn id(&self) -> Result<Id, LandslideError>;
+
+    fn parent_id(&self) -> &Id;
+
+    fn height(&self) -> u64;
+
+    fn timestamp(&self) -> &Timestamp;
+
+    // The bytes that ChainVm::parse_block turns back into this block
+    fn bytes(&self) -> Result<Vec<u8>, LandslideError>;
+
+    fn status(&self) -> Status;
+}
+
+// Everything avalanchego hands a VM when initializing it, with the host's services already dialed.
+pub struct Host {
+    pub ctx: Context,
+
+    pub genesis_bytes: Vec<u8>,
+    pub upgrade_bytes: Vec<u8>,
+    pub config_bytes: Vec<u8>,
+
+    // The most recent of the versioned databases
+    pub db: DatabaseClient<Channel>,
+    pub versioned_dbs: BTreeMap<Version, DatabaseClient<Channel>>,
+
+    pub engine: MessengerClient<Channel>,
+    pub keystore: KeystoreClient<Channel>,
+    pub shared_memory: SharedMemoryClient<Channel>,
+    pub bc_lookup: AliasReaderClient<Channel>,
+    pub sn_lookup: SubnetLookupClient<Channel>,
+    pub app_sender: AppSenderClient<Channel>,
+
+    // Cancelled when avalanchego shuts the VM down, before ChainVm::shutdown is called.
+    // Anything the VM spawns or waits on should stop once it is.
+    pub shutdown: CancellationToken,
+}
+
+// JSON-RPC 2.0 APIs, keyed by the path prefix they are served under
+pub type Handlers = HashMap<String, IoHandler>;
+
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/snowman/block/vm.go
+// and: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/common/vm.go
+//
+// Calls are never concurrent: the rpcchainvm adapter keeps the VM behind a lock.
+#[tonic::async_trait]
+pub trait ChainVm: Send + Sync + 'static {
+    type Block: Block;
+
+    // Called once, before any other call besides version and create_static_handlers.
+    // The last accepted block must exist once this returns.
+    async fn initialize(&mut self, host: Host) -> Result<(), LandslideError>;
+
+    // Builds a new block on top of the preferred block, when the engine was told one is ready
+    async fn build_block(&mut self) -> Result<Self::Block, LandslideError>;
+
+    // Parses a block from its bytes. Known blocks should come back with their current status.
+    async fn parse_block(&mut self, bytes: &[u8]) -> Result<Self::Block, LandslideError>;
+
+    // Looks up a block by its Id, whether decided or still processing
+    async fn get_block(&mut self, block_id: &Id) -> Result<Option<Self::Block>, LandslideError>;
+
+    async fn verify_block(&mut self, block: Self::Block) -> Result<(), LandslideError>;
+
+    // The host may decide a block more than once, so implementations check its status
+    // themselves (see Status::needs_decision): only processing blocks are decided,
+    // deciding a block the same way twice does nothing, and going back on a decision fails.
+    async fn accept_block(&mut self, block: Self::Block) -> Result<(), LandslideError>;
+
+    async fn reject_block(&mut self, block: Self::Block) -> Result<(), LandslideError>;
+
+    async fn set_preference(&mut self, block_id: Id) -> Result<(), LandslideError>;
+
+    async fn last_accepted(&mut self) -> Result<Id, LandslideError>;
+
+    fn version(&self) -> Version;
+
+    async fn bootstrapping(&mut self) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn bootstrapped(&mut self) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    // The APIs served under this chain's endpoint. They are given the VM itself,
+    // so they can take its lock as they need it.
+    async fn create_handlers(_vm: Arc<RwLock<Self>>) -> Result<Handlers, LandslideError>
+    where
+        Self: Sized,
+    {
+        Ok(Handlers::new())
+    }
+
+    // The APIs served under this VM's endpoint. This may be called before initialize.
+    async fn create_static_handlers(&self) -> Result<Handlers, LandslideError> {
+        Ok(Handlers::new())
+    }
+
+    // Details on the VM's health, for avalanchego's health API.
+    // Returns LandslideError::Unhealthy, with the same details, when something critical is wrong.
+    async fn health_check(&mut self) -> Result<String, LandslideError> {
+        Ok("All is well.".to_string())
+    }
+
+    // The prometheus metrics avalanchego gathers from this VM, if any
+    fn metrics_registry(&self) -> Option<&Registry> {
+        None
+    }
+
+    async fn connected(&mut self, _node_id: &[u8], _version: &str) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn disconnected(&mut self, _node_id: &[u8]) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn app_request(
+        &mut self,
+        _node_id: &[u8],
+        _request_id: u32,
+        _deadline: OffsetDateTime,
+        _request: &[u8],
+    ) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn app_request_failed(
+        &mut self,
+        _node_id: &[u8],
+        _request_id: u32,
+    ) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn app_response(
+        &mut self,
+        _node_id: &[u8],
+        _request_id: u32,
+        _response: &[u8],
+    ) -> Result<(), LandslideError> {
+        Ok(())
+    }
+
+    async fn app_gossip(&mut self, _node_id: &[u8], _msg: &[u8]) -> Result<(), LandslideError> {
+        Ok(())
+    }
+}
+
+// Returns the serialized bytes of the block with block_id, followed by its parent,
+// grandparent and so on, until either max_blocks_num blocks are collected, the
+// collected bytes would exceed max_blocks_size, or max_retrieval_time runs out.
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/snowman/block/batched_vm.go
+pub async fn get_ancestors<V: ChainVm>(
+    vm: &mut V,
+    block_id: &Id,
+    max_blocks_num: usize,
+    max_blocks_size: usize,
+    max_retrieval_time: Duration,
+) -> Result<Vec<Vec<u8>>, LandslideError> {
+    let start_time = Instant::now();
+
+    let mut block = match vm.get_block(block_id).await? {
+        Some(block) => block,
+        None => {
+            // An empty response tells the requesting node not to ask us for these ancestors
+            log::debug!("get_ancestors: block {} not found", block_id);
+            return Ok(Vec::new());
+        }
+    };
+
+    let block_bytes = block.bytes()?;
+    let mut ancestors_bytes_len = block_bytes.len() + INT_LEN;
+    let mut ancestors_bytes = vec![block_bytes];
+
+    while ancestors_bytes.len() < max_blocks_num && start_time.elapsed() < max_retrieval_time {
+        let parent_id = block.parent_id().clone();
+        block = match vm.get_block(&parent_id).await? {
+            Some(parent_block) => parent_block,
+            // reached the genesis block (or a block we don't have)
+            None => break,
+        };
+
+        let block_bytes = block.bytes()?;
+        let new_len = ancestors_bytes_len + block_bytes.len() + INT_LEN;
+        if new_len > max_blocks_size {
+            log::trace!(
+                "get_ancestors: reached maximum response size {}",
+                max_blocks_size
+            );
+            break;
+        }
+
+        ancestors_bytes.push(block_bytes);
+        ancestors_bytes_len = new_len;
+    }
+
+    log::trace!(
+        "get_ancestors: returning {} ancestors for block {}",
+        ancestors_bytes.len(),
+        block_id
+    );
+    Ok(ancestors_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        let lifecycle = Lifecycle::Initializing;
+        assert!(lifecycle.transition(Lifecycle::NormalOp).is_err());
+        assert!(lifecycle.ensure_normal_op().is_err());
+
+        let lifecycle = lifecycle.transition(Lifecycle::Bootstrapping).unwrap();
+        let lifecycle = lifecycle.transition(Lifecycle::NormalOp).unwrap();
+        assert!(lifecycle.ensure_normal_op().is_ok());
+
+        let lifecycle = lifecycle.transition(Lifecycle::Bootstrapping).unwrap();
+        let lifecycle = lifecycle.transition(Lifecycle::ShuttingDown).unwrap();
+        assert!(lifecycle.transition(Lifecycle::NormalOp).is_err());
+        assert!(lifecycle.transition(Lifecycle::ShuttingDown).is_err());
+    }
+}
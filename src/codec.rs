@@ -0,0 +1,244 @@
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/utils/wrappers/packing.go
+// and https://github.com/ava-labs/avalanchego/blob/master/codec/linearcodec/linear_codec.go
+//
+// avalanchego's linear codec writes every serialized struct as:
+//     [codec version: u16] [field 1] [field 2] ... [field n]
+// in the order the fields are declared, all integers Big-Endian, fixed-size arrays
+// as-is, and variable-length slices prefixed with their u32 length.
+use super::error::LandslideError;
+use anyhow::anyhow;
+use byteorder::{BigEndian, ByteOrder};
+
+// The only codec version in use by timestampvm
+pub const CODEC_VERSION: u16 = 0;
+
+// The largest message avalanchego's codec manager will (un)marshal
+// https://github.com/ava-labs/avalanchego/blob/master/codec/manager.go#L18
+pub const DEFAULT_MAX_SIZE: usize = 256 * 1024;
+
+const BYTE_LEN: usize = 1;
+const SHORT_LEN: usize = 2;
+const INT_LEN: usize = 4;
+const LONG_LEN: usize = 8;
+
+#[derive(Debug)]
+pub struct Packer {
+    bytes: Vec<u8>,
+    max_size: usize,
+}
+
+impl Packer {
+    pub fn new(max_size: usize) -> Packer {
+        Packer {
+            bytes: Vec::new(),
+            max_size,
+        }
+    }
+
+    // Creates a packer that has already written the codec version
+    pub fn with_version(version: u16) -> Result<Packer, LandslideError> {
+        let mut packer = Packer::new(DEFAULT_MAX_SIZE);
+        packer.pack_short(version)?;
+        Ok(packer)
+    }
+
+    pub fn pack_byte(&mut self, value: u8) -> Result<(), LandslideError> {
+        self.check_space(BYTE_LEN)?;
+        self.bytes.push(value);
+        Ok(())
+    }
+
+    pub fn pack_short(&mut self, value: u16) -> Result<(), LandslideError> {
+        self.check_space(SHORT_LEN)?;
+        let mut buf = [0; SHORT_LEN];
+        BigEndian::write_u16(&mut buf, value);
+        self.bytes.extend_from_slice(&buf);
+        Ok(())
+    }
+
+    pub fn pack_int(&mut self, value: u32) -> Result<(), LandslideError> {
+        self.check_space(INT_LEN)?;
+        let mut buf = [0; INT_LEN];
+        BigEndian::write_u32(&mut buf, value);
+        self.bytes.extend_from_slice(&buf);
+        Ok(())
+    }
+
+    pub fn pack_long(&mut self, value: u64) -> Result<(), LandslideError> {
+        self.check_space(LONG_LEN)?;
+        let mut buf = [0; LONG_LEN];
+        BigEndian::write_u64(&mut buf, value);
+        self.bytes.extend_from_slice(&buf);
+        Ok(())
+    }
+
+    // Fixed-size arrays are written without a length prefix
+    pub fn pack_fixed_bytes(&mut self, value: &[u8]) -> Result<(), LandslideError> {
+        self.check_space(value.len())?;
+        self.bytes.extend_from_slice(value);
+        Ok(())
+    }
+
+    // Slices are written with a u32 length prefix
+    pub fn pack_bytes(&mut self, value: &[u8]) -> Result<(), LandslideError> {
+        let len = u32::try_from(value.len())?;
+        self.pack_int(len)?;
+        self.pack_fixed_bytes(value)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn check_space(&self, bytes: usize) -> Result<(), LandslideError> {
+        if self.bytes.len() + bytes > self.max_size {
+            return Err(LandslideError::Codec(anyhow!(
+                "Packing {} more bytes onto {} bytes would exceed the maximum size of {} bytes.",
+                bytes,
+                self.bytes.len(),
+                self.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Unpacker<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Unpacker<'a> {
+    pub fn new(bytes: &'a [u8]) -> Unpacker<'a> {
+        Unpacker { bytes, offset: 0 }
+    }
+
+    // Creates an unpacker after reading and checking the codec version
+    pub fn with_version(
+        bytes: &'a [u8],
+        expected_version: u16,
+    ) -> Result<Unpacker<'a>, LandslideError> {
+        let mut unpacker = Unpacker::new(bytes);
+        let version = unpacker.unpack_short()?;
+        if version != expected_version {
+            return Err(LandslideError::Codec(anyhow!(
+                "Unknown codec version {}. Only version {} is supported.",
+                version,
+                expected_version
+            )));
+        }
+        Ok(unpacker)
+    }
+
+    pub fn unpack_byte(&mut self) -> Result<u8, LandslideError> {
+        Ok(self.take(BYTE_LEN)?[0])
+    }
+
+    pub fn unpack_short(&mut self) -> Result<u16, LandslideError> {
+        Ok(BigEndian::read_u16(self.take(SHORT_LEN)?))
+    }
+
+    pub fn unpack_int(&mut self) -> Result<u32, LandslideError> {
+        Ok(BigEndian::read_u32(self.take(INT_LEN)?))
+    }
+
+    pub fn unpack_long(&mut self) -> Result<u64, LandslideError> {
+        Ok(BigEndian::read_u64(self.take(LONG_LEN)?))
+    }
+
+    pub fn unpack_fixed_bytes(&mut self, len: usize) -> Result<&'a [u8], LandslideError> {
+        self.take(len)
+    }
+
+    pub fn unpack_bytes(&mut self) -> Result<&'a [u8], LandslideError> {
+        let len = usize::try_from(self.unpack_int()?)?;
+        self.take(len)
+    }
+
+    // The linear codec rejects trailing bytes, so every decode should end with this
+    pub fn done(&self) -> Result<(), LandslideError> {
+        if self.offset != self.bytes.len() {
+            return Err(LandslideError::Codec(anyhow!(
+                "Unpacked {} bytes, but {} bytes were provided. Trailing bytes are not allowed.",
+                self.offset,
+                self.bytes.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LandslideError> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| {
+                LandslideError::Codec(anyhow!(
+                    "Unable to unpack {} bytes at offset {} from {} bytes: insufficient length.",
+                    len,
+                    self.offset,
+                    self.bytes.len()
+                ))
+            })?;
+
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pack_then_unpack() {
+        let mut packer = Packer::with_version(CODEC_VERSION).unwrap();
+        packer.pack_byte(7).unwrap();
+        packer.pack_int(0x01020304).unwrap();
+        packer.pack_long(u64::MAX - 1).unwrap();
+        packer.pack_fixed_bytes(&[9, 9]).unwrap();
+        packer.pack_bytes(b"hello").unwrap();
+        let bytes = packer.into_bytes();
+
+        assert_eq!(
+            bytes,
+            vec![
+                0, 0, // version
+                7, // byte
+                1, 2, 3, 4, // int
+                255, 255, 255, 255, 255, 255, 255, 254, // long
+                9, 9, // fixed bytes
+                0, 0, 0, 5, b'h', b'e', b'l', b'l', b'o', // length-prefixed bytes
+            ]
+        );
+
+        let mut unpacker = Unpacker::with_version(&bytes, CODEC_VERSION).unwrap();
+        assert_eq!(unpacker.unpack_byte().unwrap(), 7);
+        assert_eq!(unpacker.unpack_int().unwrap(), 0x01020304);
+        assert_eq!(unpacker.unpack_long().unwrap(), u64::MAX - 1);
+        assert_eq!(unpacker.unpack_fixed_bytes(2).unwrap(), &[9, 9]);
+        assert_eq!(unpacker.unpack_bytes().unwrap(), b"hello");
+        unpacker.done().unwrap();
+    }
+
+    #[test]
+    fn test_unpack_rejects_bad_input() {
+        assert!(Unpacker::with_version(&[0, 1], CODEC_VERSION).is_err());
+
+        let mut unpacker = Unpacker::new(&[0, 0, 0, 10, 1, 2]);
+        assert!(unpacker.unpack_bytes().is_err());
+
+        let mut unpacker = Unpacker::new(&[1, 2, 3]);
+        unpacker.unpack_short().unwrap();
+        assert!(unpacker.done().is_err());
+    }
+
+    #[test]
+    fn test_pack_respects_max_size() {
+        let mut packer = Packer::new(3);
+        packer.pack_short(1).unwrap();
+        assert!(packer.pack_short(2).is_err());
+    }
+}
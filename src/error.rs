@@ -1,19 +1,78 @@
-use super::id::Id;
+use super::chainvm::{Lifecycle, Status as BlockStatus};
+use super::id::{Id, BYTE_LENGTH};
+use jsonrpc_core::error::{Error as JsonRpcError, ErrorCode};
 use thiserror::Error as ThisError;
-use tonic::Status;
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Status};
 
+// The gRPC metadata key carrying LandslideError::reason, for the host to tell errors apart by
+pub const REASON_METADATA_KEY: &str = "landslide-reason";
+
+// Everything past the message (like backtraces captured by anyhow) only goes to the logs.
 pub fn into_status(err: LandslideError) -> tonic::Status {
-    tonic::Status::unknown(format!("{:?}", err))
+    let code = err.code();
+    let reason = err.reason();
+    log_error(&err, code);
+
+    let message = match err {
+        // the status the host sent us already says what it needs to
+        LandslideError::Status(status) => return *status,
+        // the details are meant to be read as they are
+        LandslideError::Unhealthy { details } => details,
+        err => err.to_string(),
+    };
+
+    let mut status = tonic::Status::new(code, message);
+    status
+        .metadata_mut()
+        .insert(REASON_METADATA_KEY, MetadataValue::from_static(reason));
+    status
 }
 
 pub fn into_jsonrpc_error(err: LandslideError) -> jsonrpc_core::error::Error {
-    jsonrpc_core::error::Error::invalid_params(format!("{}", err))
+    let code = err.code();
+    log_error(&err, code);
+
+    JsonRpcError {
+        code: match code {
+            Code::InvalidArgument => ErrorCode::InvalidParams,
+            _ => ErrorCode::ServerError(-32000),
+        },
+        message: err.to_string(),
+        data: Some(serde_json::Value::String(err.reason().to_string())),
+    }
+}
+
+fn log_error(err: &LandslideError, code: Code) {
+    match code {
+        Code::Internal | Code::Unknown => log::error!("{:?}", err),
+        _ => log::debug!("{:?}", err),
+    }
 }
 
 #[derive(Debug, ThisError)]
 pub enum LandslideError {
     #[error("No parent block with id {parent_block_id} found for block with id {block_id}. All blocks have parents (since the genesis block is bootstrapped especially for this purpose). This block is invalid.")]
     NoParentBlock { block_id: Id, parent_block_id: Id },
+    #[error("Block with id {block_id} was not found.")]
+    BlockNotFound { block_id: Id },
+    #[error("Block with id {block_id} is invalid: {reason}")]
+    InvalidBlock { block_id: Id, reason: String },
+    #[error("Unable to convert a slice of bytes of length {length} into an Id which expects a length of {} bytes", BYTE_LENGTH)]
+    InvalidIdLength { length: usize },
+    #[error("Block with id {block_id} was already decided as {status:?}.")]
+    BlockAlreadyDecided { block_id: Id, status: BlockStatus },
+    #[error("Invalid {argument}: {source}")]
+    InvalidArgument {
+        argument: &'static str,
+        source: Box<LandslideError>,
+    },
+    #[error("Preferred block {block_id} is {status:?}, and not the last accepted block, so no block can be built on it.")]
+    PreferredNotBuildable { block_id: Id, status: BlockStatus },
+    #[error("There is nothing in the mempool to build a block with.")]
+    NothingToBuild,
+    #[error("The database was closed when calling {method}.")]
+    DatabaseClosed { method: String },
     #[error("No ports were available to bind the plugin's gRPC server to.")]
     NoTCPPortAvailable,
     #[error("This executable is meant to be a go-plugin to other processes. Do not run this directly. The Magic Handshake failed.")]
@@ -35,7 +94,7 @@ pub enum LandslideError {
     #[error("Error with tonic (gRPC) transport: {0}")]
     TonicTransportError(#[from] tonic::transport::Error),
     #[error(transparent)]
-    Status(#[from] Status),
+    Status(Box<Status>),
     #[error("Parent block height, {parent_block_height}, should have been exactly 1 greater than the block's height {block_height}. This block is invalid.")]
     ParentBlockHeightUnexpected {
         block_height: u64,
@@ -53,4 +112,190 @@ pub enum LandslideError {
     TryFromInt(#[from] std::num::TryFromIntError),
     #[error(transparent)]
     Encoding(anyhow::Error),
+    #[error(transparent)]
+    Codec(anyhow::Error),
+    #[error("The VM can't go from {from:?} to {to:?}.")]
+    LifecycleTransition { from: Lifecycle, to: Lifecycle },
+    #[error("The VM is {lifecycle:?}, and can only do this in normal operation. Try again later.")]
+    NotInNormalOp { lifecycle: Lifecycle },
+    #[error("The VM is unhealthy: {details}")]
+    Unhealthy { details: String },
+    #[error("Invalid VM configuration: {0}")]
+    Config(anyhow::Error),
+    #[error("Error registering or recording metrics: {0}")]
+    Prometheus(#[from] prometheus::Error),
+    #[error("Error in the embedded database: {0}")]
+    Sled(#[from] sled::Error),
+    #[error(
+        "The mempool is full, with {max_size} proposals waiting to be built. Try again later."
+    )]
+    MempoolFull { max_size: usize },
+    #[error("Data with id {id} was already proposed, and is waiting to be built or accepted.")]
+    MempoolDuplicate { id: Id },
+    #[error("Request {request_id} to node {node_id} failed, or timed out before it was answered.")]
+    AppRequestFailed { node_id: String, request_id: u32 },
+    #[error("App message type {message_type} is not registered.")]
+    UnknownAppMessageType { message_type: u16 },
+    #[error("No peers are connected to send the request to. Try again later.")]
+    NoPeersConnected,
+}
+
+impl LandslideError {
+    // The gRPC status code the host gets for this error
+    pub fn code(&self) -> Code {
+        match self {
+            Self::BlockNotFound { .. } => Code::NotFound,
+
+            Self::NoParentBlock { .. }
+            | Self::ParentBlockHeightUnexpected { .. }
+            | Self::InvalidBlock { .. }
+            | Self::InvalidIdLength { .. }
+            | Self::FromHexError(_)
+            | Self::SerdeJsonError(_)
+            | Self::FromUtf8(_)
+            | Self::Base58Decode(_)
+            | Self::InvalidArgument { .. }
+            | Self::TimeErrorComponentRange(_)
+            | Self::Encoding(_)
+            | Self::Codec(_)
+            | Self::MempoolDuplicate { .. }
+            | Self::UnknownAppMessageType { .. } => Code::InvalidArgument,
+
+            Self::StateNotInitialized
+            | Self::NotInNormalOp { .. }
+            | Self::LifecycleTransition { .. }
+            | Self::NothingToBuild
+            | Self::BlockAlreadyDecided { .. }
+            | Self::PreferredNotBuildable { .. }
+            | Self::Config(_) => Code::FailedPrecondition,
+
+            Self::DatabaseClosed { .. }
+            | Self::Unhealthy { .. }
+            | Self::MempoolFull { .. }
+            | Self::AppRequestFailed { .. }
+            | Self::NoPeersConnected
+            | Self::TonicTransportError(_) => Code::Unavailable,
+
+            Self::Status(status) => status.code(),
+
+            Self::NoTCPPortAvailable
+            | Self::GRPCHandshakeMagicCookieValueMismatch
+            | Self::StdIoError(_)
+            | Self::SetLoggerError(_)
+            | Self::Other(_)
+            // conversions of what was sent in are checked where it comes in, as InvalidArgument
+            | Self::TryFromSlice(_)
+            | Self::TryFromInt(_)
+            | Self::GrrPlugin(_)
+            | Self::Prometheus(_)
+            | Self::Sled(_) => Code::Internal,
+        }
+    }
+
+    // A stable, machine-readable name for this error, that doesn't change with its message
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NoParentBlock { .. } => "NO_PARENT_BLOCK",
+            Self::BlockNotFound { .. } => "BLOCK_NOT_FOUND",
+            Self::InvalidBlock { .. } => "INVALID_BLOCK",
+            Self::InvalidIdLength { .. } => "INVALID_ID_LENGTH",
+            Self::NothingToBuild => "NOTHING_TO_BUILD",
+            Self::BlockAlreadyDecided { .. } => "BLOCK_ALREADY_DECIDED",
+            Self::PreferredNotBuildable { .. } => "PREFERRED_NOT_BUILDABLE",
+            Self::DatabaseClosed { .. } => "DATABASE_CLOSED",
+            Self::NoTCPPortAvailable => "NO_TCP_PORT_AVAILABLE",
+            Self::GRPCHandshakeMagicCookieValueMismatch => "HANDSHAKE_MISMATCH",
+            Self::StateNotInitialized => "STATE_NOT_INITIALIZED",
+            Self::FromHexError(_) => "INVALID_HEX",
+            Self::SerdeJsonError(_) => "INVALID_JSON",
+            Self::FromUtf8(_) => "INVALID_UTF8",
+            Self::StdIoError(_) => "IO",
+            Self::SetLoggerError(_) => "SET_LOGGER",
+            Self::Other(_) => "INTERNAL",
+            Self::TonicTransportError(_) => "TRANSPORT",
+            Self::Status(_) => "HOST_STATUS",
+            Self::ParentBlockHeightUnexpected { .. } => "PARENT_BLOCK_HEIGHT_UNEXPECTED",
+            Self::TimeErrorComponentRange(_) => "INVALID_TIME",
+            Self::Base58Decode(_) => "INVALID_BASE58",
+            Self::GrrPlugin(_) => "PLUGIN",
+            Self::TryFromSlice(_) | Self::TryFromInt(_) => "CONVERSION",
+            Self::InvalidArgument { .. } => "INVALID_ARGUMENT",
+            Self::Encoding(_) => "INVALID_ENCODING",
+            Self::Codec(_) => "INVALID_CODEC",
+            Self::Prometheus(_) => "METRICS",
+            Self::Sled(_) => "EMBEDDED_DATABASE",
+            Self::MempoolFull { .. } => "MEMPOOL_FULL",
+            Self::MempoolDuplicate { .. } => "MEMPOOL_DUPLICATE",
+            Self::AppRequestFailed { .. } => "APP_REQUEST_FAILED",
+            Self::UnknownAppMessageType { .. } => "UNKNOWN_APP_MESSAGE_TYPE",
+            Self::NoPeersConnected => "NO_PEERS_CONNECTED",
+            Self::LifecycleTransition { .. } => "LIFECYCLE_TRANSITION",
+            Self::NotInNormalOp { .. } => "NOT_IN_NORMAL_OP",
+            Self::Unhealthy { .. } => "UNHEALTHY",
+            Self::Config(_) => "INVALID_CONFIG",
+        }
+    }
+}
+
+// tonic::Status is large enough to bloat every Result carrying a LandslideError,
+// so it is boxed and converted by hand instead of through #[from].
+impl From<Status> for LandslideError {
+    fn from(status: Status) -> Self {
+        LandslideError::Status(Box::new(status))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_into_status() {
+        let status = into_status(LandslideError::BlockNotFound {
+            block_id: Id::new([0; 32]),
+        });
+        assert_eq!(status.code(), Code::NotFound);
+        assert_eq!(
+            status.metadata().get(REASON_METADATA_KEY).unwrap(),
+            "BLOCK_NOT_FOUND"
+        );
+
+        // a failed conversion is only the caller's fault where it is said to be
+        let conversion = <[u8; 2]>::try_from(&[0u8][..]).unwrap_err();
+        assert_eq!(LandslideError::from(conversion).code(), Code::Internal);
+        let status = into_status(LandslideError::InvalidArgument {
+            argument: "data",
+            source: Box::new(conversion.into()),
+        });
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert!(status.message().starts_with("Invalid data: "));
+
+        let status = into_status(LandslideError::StateNotInitialized);
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        let status = into_status(LandslideError::DatabaseClosed {
+            method: "get".to_string(),
+        });
+        assert_eq!(status.code(), Code::Unavailable);
+
+        // only the message reaches the host, not the context chain or backtrace
+        let err = anyhow::anyhow!("root cause").context("while doing something");
+        let status = into_status(LandslideError::Other(err));
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(status.message(), "while doing something");
+
+        let status = into_status(LandslideError::Status(Box::new(Status::aborted("host"))));
+        assert_eq!(status.code(), Code::Aborted);
+    }
+
+    #[test]
+    fn test_into_jsonrpc_error() {
+        let err = into_jsonrpc_error(LandslideError::InvalidIdLength { length: 3 });
+        assert_eq!(err.code, ErrorCode::InvalidParams);
+        assert_eq!(err.data, Some(serde_json::json!("INVALID_ID_LENGTH")));
+
+        let err = into_jsonrpc_error(LandslideError::NothingToBuild);
+        assert_eq!(err.code, ErrorCode::ServerError(-32000));
+        assert_eq!(err.data, Some(serde_json::json!("NOTHING_TO_BUILD")));
+    }
 }
@@ -0,0 +1,458 @@
+// The key-value storage a VM keeps its state in.
+// In production that is the database avalanchego serves over rpcdb, but the same
+// VM can run against an in-memory store (for tests) or an embedded on-disk store
+// (to run standalone, without an avalanchego host).
+use super::error::LandslideError;
+use super::proto::rpcdb::database_client::*;
+use super::proto::rpcdb::*;
+use super::proto::DatabaseError;
+use anyhow::anyhow;
+use async_stream::try_stream;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+use num::FromPrimitive;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::Poll;
+use tonic::transport::Channel;
+
+// A key and its value, as yielded by a KeyValueStream
+pub type KeyValue = (Vec<u8>, Vec<u8>);
+
+// The key-value pairs of an iteration, in key order
+pub type KeyValueStream = BoxStream<'static, Result<KeyValue, LandslideError>>;
+
+#[tonic::async_trait]
+pub trait KeyValueStore: Debug + Send + Sync + 'static {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, LandslideError>;
+
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), LandslideError>;
+
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), LandslideError>;
+
+    // Applies every put and delete atomically: either all of them are written, or none are.
+    async fn write_batch(
+        &mut self,
+        puts: Vec<KeyValue>,
+        deletes: Vec<Vec<u8>>,
+    ) -> Result<(), LandslideError>;
+
+    // Iterates, in key order, over every key-value pair whose key starts with
+    // prefix and is greater than or equal to start.
+    async fn iterate_with_start_and_prefix(
+        &mut self,
+        start: Vec<u8>,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError>;
+
+    async fn close(&mut self) -> Result<(), LandslideError>;
+}
+
+// The database avalanchego serves to the VM over gRPC
+#[derive(Debug)]
+pub struct RpcDb {
+    db: DatabaseClient<Channel>,
+
+    // Every WriteBatchRequest carries an id, so the database can
+    // reassemble batches that are split across several requests.
+    next_batch_id: i64,
+}
+
+impl RpcDb {
+    pub fn new(db: DatabaseClient<Channel>) -> RpcDb {
+        RpcDb {
+            db,
+            next_batch_id: 0,
+        }
+    }
+
+    fn check_error(method: &str, err: u32) -> Result<(), LandslideError> {
+        let dberr = DatabaseError::from_u32(err);
+        match dberr {
+            Some(DatabaseError::None) => Ok(()),
+            Some(DatabaseError::Closed) => Err(LandslideError::DatabaseClosed {
+                method: method.to_string(),
+            }),
+            Some(DatabaseError::NotFound) => Err(LandslideError::Other(anyhow!(
+                "DatabaseClient::{} returned with error: {:?}.",
+                method,
+                dberr
+            ))),
+            None => Err(LandslideError::Other(anyhow!(
+                "DatabaseClient::{} returned with unknown error: {}.",
+                method,
+                err
+            ))),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl KeyValueStore for RpcDb {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, LandslideError> {
+        let get_response = self.db.get(GetRequest { key }).await?.into_inner();
+
+        match DatabaseError::from_u32(get_response.err) {
+            Some(DatabaseError::None) => Ok(Some(get_response.value)),
+            Some(DatabaseError::NotFound) => Ok(None),
+            // Closed, or a code this client doesn't know
+            _ => Self::check_error("get", get_response.err).map(|_| None),
+        }
+    }
+
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), LandslideError> {
+        let put_response = self.db.put(PutRequest { key, value }).await?.into_inner();
+        Self::check_error("put", put_response.err)
+    }
+
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), LandslideError> {
+        let delete_response = self.db.delete(DeleteRequest { key }).await?.into_inner();
+        Self::check_error("delete", delete_response.err)
+    }
+
+    async fn write_batch(
+        &mut self,
+        puts: Vec<KeyValue>,
+        deletes: Vec<Vec<u8>>,
+    ) -> Result<(), LandslideError> {
+        let id = self.next_batch_id;
+        self.next_batch_id += 1;
+
+        let write_batch_response = self
+            .db
+            .write_batch(WriteBatchRequest {
+                puts: puts
+                    .into_iter()
+                    .map(|(key, value)| PutRequest { key, value })
+                    .collect(),
+                deletes: deletes
+                    .into_iter()
+                    .map(|key| DeleteRequest { key })
+                    .collect(),
+                id,
+                continues: false,
+            })
+            .await?
+            .into_inner();
+
+        Self::check_error("write_batch", write_batch_response.err)
+    }
+
+    async fn iterate_with_start_and_prefix(
+        &mut self,
+        start: Vec<u8>,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError> {
+        let id = self
+            .db
+            .new_iterator_with_start_and_prefix(NewIteratorWithStartAndPrefixRequest {
+                start,
+                prefix,
+            })
+            .await?
+            .into_inner()
+            .id;
+
+        Ok(RpcDbIterator::new(self.db.clone(), id).boxed())
+    }
+
+    async fn close(&mut self) -> Result<(), LandslideError> {
+        let close_response = self.db.close(CloseRequest {}).await?.into_inner();
+        Self::check_error("close", close_response.err)
+    }
+}
+
+// A Stream of the key-value pairs of a server-side database iterator.
+// Pairs are fetched from the database a page at a time with IteratorNext.
+// Once the iterator is exhausted, IteratorError is checked so that a failed
+// iteration surfaces as an error rather than as a silently truncated stream.
+// The server-side iterator is released when the stream is dropped.
+struct RpcDbIterator {
+    db: DatabaseClient<Channel>,
+    id: u64,
+    inner: KeyValueStream,
+}
+
+impl RpcDbIterator {
+    fn new(db: DatabaseClient<Channel>, id: u64) -> RpcDbIterator {
+        let mut next_db = db.clone();
+        let inner = try_stream! {
+            loop {
+                let data = next_db
+                    .iterator_next(IteratorNextRequest { id })
+                    .await?
+                    .into_inner()
+                    .data;
+
+                if data.is_empty() {
+                    break;
+                }
+
+                for pair in data {
+                    yield (pair.key, pair.value);
+                }
+            }
+
+            let iterator_error_response = next_db
+                .iterator_error(IteratorErrorRequest { id })
+                .await?
+                .into_inner();
+            RpcDb::check_error("iterator_error", iterator_error_response.err)?;
+        };
+
+        RpcDbIterator {
+            db,
+            id,
+            inner: inner.boxed(),
+        }
+    }
+}
+
+impl Stream for RpcDbIterator {
+    type Item = Result<KeyValue, LandslideError>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+impl Drop for RpcDbIterator {
+    // Drop can't await, so the release is sent from a task of its own.
+    fn drop(&mut self) {
+        let mut db = self.db.clone();
+        let id = self.id;
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    if let Err(err) = db.iterator_release(IteratorReleaseRequest { id }).await {
+                        log::error!("Unable to release database iterator {}: {:?}", id, err);
+                    }
+                });
+            }
+            Err(_) => log::error!(
+                "Database iterator {} dropped outside a tokio runtime and was not released.",
+                id
+            ),
+        }
+    }
+}
+
+// Keeps everything in memory, and forgets it all when dropped.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+#[tonic::async_trait]
+impl KeyValueStore for MemoryStore {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, LandslideError> {
+        Ok(self.entries.get(&key).cloned())
+    }
+
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), LandslideError> {
+        self.entries.insert(key, value);
+        Ok(())
+    }
+
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), LandslideError> {
+        self.entries.remove(&key);
+        Ok(())
+    }
+
+    async fn write_batch(
+        &mut self,
+        puts: Vec<KeyValue>,
+        deletes: Vec<Vec<u8>>,
+    ) -> Result<(), LandslideError> {
+        self.entries.extend(puts);
+        for key in deletes {
+            self.entries.remove(&key);
+        }
+        Ok(())
+    }
+
+    // Iterates over a snapshot, so writes made while iterating aren't seen.
+    async fn iterate_with_start_and_prefix(
+        &mut self,
+        start: Vec<u8>,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError> {
+        let pairs: Vec<Result<KeyValue, LandslideError>> = self
+            .entries
+            .range(start.max(prefix.clone())..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .map(|(key, value)| Ok((key.clone(), value.clone())))
+            .collect();
+
+        Ok(stream::iter(pairs).boxed())
+    }
+
+    async fn close(&mut self) -> Result<(), LandslideError> {
+        Ok(())
+    }
+}
+
+// An embedded on-disk database, for running the VM without an avalanchego host.
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SledStore, LandslideError> {
+        Ok(SledStore {
+            db: sled::open(path)?,
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl KeyValueStore for SledStore {
+    async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, LandslideError> {
+        Ok(self.db.get(key)?.map(|value| value.to_vec()))
+    }
+
+    async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), LandslideError> {
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+
+    async fn delete(&mut self, key: Vec<u8>) -> Result<(), LandslideError> {
+        self.db.remove(key)?;
+        Ok(())
+    }
+
+    async fn write_batch(
+        &mut self,
+        puts: Vec<KeyValue>,
+        deletes: Vec<Vec<u8>>,
+    ) -> Result<(), LandslideError> {
+        let mut batch = sled::Batch::default();
+        for (key, value) in puts {
+            batch.insert(key, value);
+        }
+        for key in deletes {
+            batch.remove(key);
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+
+    async fn iterate_with_start_and_prefix(
+        &mut self,
+        start: Vec<u8>,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError> {
+        let pairs = self
+            .db
+            .range(start.max(prefix.clone())..)
+            .take_while(move |result| match result {
+                Ok((key, _)) => key.starts_with(&prefix),
+                Err(_) => true,
+            })
+            .map(|result| {
+                let (key, value) = result?;
+                Ok((key.to_vec(), value.to_vec()))
+            });
+
+        Ok(stream::iter(pairs).boxed())
+    }
+
+    async fn close(&mut self) -> Result<(), LandslideError> {
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::TryStreamExt;
+
+    async fn test_store<S: KeyValueStore>(mut store: S) {
+        assert_eq!(store.get(b"a1".to_vec()).await.unwrap(), None);
+
+        store.put(b"a1".to_vec(), b"one".to_vec()).await.unwrap();
+        assert_eq!(
+            store.get(b"a1".to_vec()).await.unwrap(),
+            Some(b"one".to_vec())
+        );
+
+        store
+            .write_batch(
+                vec![
+                    (b"a2".to_vec(), b"two".to_vec()),
+                    (b"a3".to_vec(), b"three".to_vec()),
+                    (b"b1".to_vec(), b"other".to_vec()),
+                ],
+                vec![b"a1".to_vec()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.get(b"a1".to_vec()).await.unwrap(), None);
+
+        let pairs: Vec<KeyValue> = store
+            .iterate_with_start_and_prefix(Vec::new(), b"a".to_vec())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            pairs,
+            vec![
+                (b"a2".to_vec(), b"two".to_vec()),
+                (b"a3".to_vec(), b"three".to_vec()),
+            ]
+        );
+
+        let pairs: Vec<KeyValue> = store
+            .iterate_with_start_and_prefix(b"a3".to_vec(), b"a".to_vec())
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(pairs, vec![(b"a3".to_vec(), b"three".to_vec())]);
+
+        store.delete(b"b1".to_vec()).await.unwrap();
+        assert_eq!(store.get(b"b1".to_vec()).await.unwrap(), None);
+
+        store.close().await.unwrap();
+    }
+
+    #[test]
+    fn test_rpcdb_check_error() {
+        assert!(RpcDb::check_error("get", DatabaseError::None as u32).is_ok());
+        assert!(matches!(
+            RpcDb::check_error("get", DatabaseError::Closed as u32),
+            Err(LandslideError::DatabaseClosed { .. })
+        ));
+        assert!(matches!(
+            RpcDb::check_error("get", 42),
+            Err(LandslideError::Other(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        test_store(MemoryStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sled_store() {
+        let dir = tempfile::tempdir().unwrap();
+        test_store(SledStore::open(dir.path()).unwrap()).await;
+    }
+}
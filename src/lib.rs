@@ -0,0 +1,13 @@
+// Common modules required by any VM
+pub mod appsender;
+pub mod chainvm;
+pub mod codec;
+pub mod context;
+pub mod encoding;
+pub mod error;
+pub mod id;
+pub mod kvstore;
+pub mod metrics;
+pub mod proto;
+pub mod rpcchainvm;
+pub mod timestamp;
@@ -1,64 +1,40 @@
-// Common modules required by any VM
-pub mod appsender;
-pub mod context;
-pub mod encoding;
-pub mod error;
-pub mod id;
-pub mod proto;
-
 // timestamp VM
 mod timestampvm;
 
 use anyhow::{Context, Result};
-use grr_plugin::{HandshakeConfig, Server};
-use proto::vm_proto::vm_server::VmServer;
+use landslide::kvstore::SledStore;
+use landslide::rpcchainvm;
 use std::env;
 use std::error::Error;
-use std::sync::Arc;
 use timestampvm::TimestampVm;
-use tokio::sync::Mutex;
 
 const LANDSLIDE_LOG_CONFIG_FILE: &str = "LANDSLIDE_LOG_CONFIG_FILE";
 
-//https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm.go#L19
-const AVALANCHE_VM_PROTOCOL_VERSION: u32 = 9;
-
-// https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm.go#L20
-const MAGIC_COOKIE_KEY: &str = "VM_PLUGIN";
-const MAGIC_COOKIE_VALUE: &str = "dynamic";
+// When set, the chain is kept in an embedded database in this directory,
+// instead of in the database avalanchego provides.
+const LANDSLIDE_DB_DIR: &str = "LANDSLIDE_DB_DIR";
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     init_logger();
 
-    log::info!("creating grr-plugin (go-plugin) Server...");
-    let mut plugin = Server::new(
-        AVALANCHE_VM_PROTOCOL_VERSION,
-        HandshakeConfig {
-            magic_cookie_key: MAGIC_COOKIE_KEY.to_string(),
-            magic_cookie_value: MAGIC_COOKIE_VALUE.to_string(),
-        },
-    ).with_context(|| format!("Error creating the plugin server with Avalanche protocol version {} and Handshake configuration with magic_cookie_key: {} and magic_cookie_value: {}", AVALANCHE_VM_PROTOCOL_VERSION, MAGIC_COOKIE_KEY, MAGIC_COOKIE_VALUE))?;
-
-    // extract the JSON-RPC Broker
-    let grpc_broker = Arc::new(Mutex::new(plugin.grpc_broker().await?));
-
-    let tsvm = TimestampVm::new(grpc_broker).context("Unable to create TimestampVm")?;
-
-    log::info!("Initialized the timestampvm logger");
-    let vm = VmServer::new(tsvm);
-    log::info!("TimestampVm Service Created");
-
-    plugin
-        .serve(vm)
-        .await
-        .context("Error serving the plugin vm using the grr-plugin scaffolding server")?;
+    if let Ok(db_dir) = env::var(LANDSLIDE_DB_DIR) {
+        log::info!("Using the embedded database in {}", db_dir);
+        let store = SledStore::open(&db_dir)
+            .with_context(|| format!("Unable to open the embedded database in {}", db_dir))?;
+        let tsvm = TimestampVm::with_store(store).context("Unable to create TimestampVm")?;
+        rpcchainvm::serve(tsvm).await?;
+    } else {
+        let tsvm = TimestampVm::new().context("Unable to create TimestampVm")?;
+        rpcchainvm::serve(tsvm).await?;
+    }
 
     Ok(())
 }
 
 fn init_logger() {
     // is there a RUST_LOG environment variable?
+    // The file's levels are kept as they are, so a chain's config can lower them, but never raise them.
     if let Ok(log_config_file_path) = env::var(LANDSLIDE_LOG_CONFIG_FILE) {
         log4rs::init_file(log_config_file_path, Default::default()).unwrap();
     } else {
@@ -73,10 +49,12 @@ fn init_logger() {
             .build(
                 log4rs::config::runtime::Root::builder()
                     .appender("stderr")
-                    .build(log::LevelFilter::Info),
+                    .build(log::LevelFilter::Trace),
             )
             .unwrap();
 
         log4rs::init_config(config).unwrap();
+        // Filtered here rather than by the root logger, so a chain's config can change it
+        log::set_max_level(log::LevelFilter::Info);
     }
 }
@@ -0,0 +1,160 @@
+// Converts metrics gathered from a prometheus Registry into the protobuf MetricFamily
+// that avalanchego expects from Vm::gather.
+// The proto mirrors: https://github.com/prometheus/client_model/blob/master/io/prometheus/client/metrics.proto
+use super::proto::io::prometheus::client as pb;
+use prometheus::proto as prom;
+use prometheus::Registry;
+
+pub fn gather(registry: &Registry) -> Vec<pb::MetricFamily> {
+    registry.gather().iter().map(metric_family).collect()
+}
+
+fn metric_family(mf: &prom::MetricFamily) -> pb::MetricFamily {
+    let metric_type = mf.get_field_type();
+
+    pb::MetricFamily {
+        name: Some(mf.get_name().to_string()),
+        help: Some(mf.get_help().to_string()),
+        r#type: Some(self::metric_type(metric_type) as i32),
+        metric: mf
+            .get_metric()
+            .iter()
+            .map(|m| metric(m, metric_type))
+            .collect(),
+    }
+}
+
+fn metric_type(metric_type: prom::MetricType) -> pb::MetricType {
+    match metric_type {
+        prom::MetricType::COUNTER => pb::MetricType::Counter,
+        prom::MetricType::GAUGE => pb::MetricType::Gauge,
+        prom::MetricType::SUMMARY => pb::MetricType::Summary,
+        prom::MetricType::UNTYPED => pb::MetricType::Untyped,
+        prom::MetricType::HISTOGRAM => pb::MetricType::Histogram,
+    }
+}
+
+// Only the value matching the family's type is set, the same way the golang client does.
+fn metric(m: &prom::Metric, metric_type: prom::MetricType) -> pb::Metric {
+    let mut metric = pb::Metric {
+        label: m
+            .get_label()
+            .iter()
+            .map(|lp| pb::LabelPair {
+                name: Some(lp.get_name().to_string()),
+                value: Some(lp.get_value().to_string()),
+            })
+            .collect(),
+        gauge: None,
+        counter: None,
+        summary: None,
+        untyped: None,
+        histogram: None,
+        timestamp_ms: match m.get_timestamp_ms() {
+            0 => None,
+            ts => Some(ts),
+        },
+    };
+
+    match metric_type {
+        prom::MetricType::COUNTER => {
+            metric.counter = Some(pb::Counter {
+                value: Some(m.get_counter().get_value()),
+                exemplar: None,
+            })
+        }
+        prom::MetricType::GAUGE => {
+            metric.gauge = Some(pb::Gauge {
+                value: Some(m.get_gauge().get_value()),
+            })
+        }
+        prom::MetricType::UNTYPED => {
+            metric.untyped = Some(pb::Untyped {
+                value: Some(m.get_untyped().get_value()),
+            })
+        }
+        prom::MetricType::SUMMARY => {
+            let summary = m.get_summary();
+            metric.summary = Some(pb::Summary {
+                sample_count: Some(summary.get_sample_count()),
+                sample_sum: Some(summary.get_sample_sum()),
+                quantile: summary
+                    .get_quantile()
+                    .iter()
+                    .map(|q| pb::Quantile {
+                        quantile: Some(q.get_quantile()),
+                        value: Some(q.get_value()),
+                    })
+                    .collect(),
+            })
+        }
+        prom::MetricType::HISTOGRAM => {
+            let histogram = m.get_histogram();
+            metric.histogram = Some(pb::Histogram {
+                sample_count: Some(histogram.get_sample_count()),
+                sample_sum: Some(histogram.get_sample_sum()),
+                bucket: histogram
+                    .get_bucket()
+                    .iter()
+                    .map(|b| pb::Bucket {
+                        cumulative_count: Some(b.get_cumulative_count()),
+                        upper_bound: Some(b.get_upper_bound()),
+                        exemplar: None,
+                    })
+                    .collect(),
+            })
+        }
+    }
+
+    metric
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prometheus::{Histogram, HistogramOpts, IntCounterVec, Opts};
+
+    #[test]
+    fn test_gather_counter() {
+        let registry = Registry::new();
+        let counter =
+            IntCounterVec::new(Opts::new("calls", "number of calls"), &["method"]).unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+
+        counter.with_label_values(&["getBlock"]).inc_by(3);
+
+        let families = gather(&registry);
+        assert_eq!(families.len(), 1);
+
+        let family = &families[0];
+        assert_eq!(family.name.as_deref(), Some("calls"));
+        assert_eq!(family.r#type, Some(pb::MetricType::Counter as i32));
+        assert_eq!(family.metric.len(), 1);
+
+        let metric = &family.metric[0];
+        assert_eq!(metric.label[0].name.as_deref(), Some("method"));
+        assert_eq!(metric.label[0].value.as_deref(), Some("getBlock"));
+        assert_eq!(metric.counter.as_ref().unwrap().value, Some(3.0));
+        assert!(metric.histogram.is_none());
+    }
+
+    #[test]
+    fn test_gather_histogram() {
+        let registry = Registry::new();
+        let histogram =
+            Histogram::with_opts(HistogramOpts::new("latency", "latency").buckets(vec![1.0, 2.0]))
+                .unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+
+        histogram.observe(0.5);
+        histogram.observe(1.5);
+
+        let families = gather(&registry);
+        let histogram = families[0].metric[0].histogram.as_ref().unwrap();
+        assert_eq!(histogram.sample_count, Some(2));
+        assert_eq!(histogram.sample_sum, Some(2.0));
+        assert_eq!(histogram.bucket.len(), 2);
+        assert_eq!(histogram.bucket[0].cumulative_count, Some(1));
+        assert_eq!(histogram.bucket[1].cumulative_count, Some(2));
+    }
+}
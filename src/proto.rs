@@ -64,6 +64,7 @@ use num_derive::FromPrimitive;
 use serde_json::Value;
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 use tonic::transport::Channel;
 use tonic::{Request, Response, Status};
 
@@ -94,16 +95,20 @@ pub enum DatabaseError {
 pub struct GHttpServer {
     grpc_broker: Arc<Mutex<GRpcBroker>>,
     io_handler: IoHandler,
+    // Once cancelled (as the VM shuts down), requests are refused
+    shutdown: CancellationToken,
 }
 
 impl GHttpServer {
     pub fn new_server(
         grpc_broker: Arc<Mutex<GRpcBroker>>,
         io_handler: IoHandler,
+        shutdown: CancellationToken,
     ) -> HttpServer<GHttpServer> {
         HttpServer::new(GHttpServer {
             grpc_broker,
             io_handler,
+            shutdown,
         })
     }
 
@@ -167,14 +172,20 @@ impl GHttpServer {
 #[tonic::async_trait]
 impl ghttp::http_server::Http for GHttpServer {
     async fn handle(&self, req: Request<HttpRequest>) -> Result<Response<HttpResponse>, Status> {
+        if self.shutdown.is_cancelled() {
+            return Err(Status::unavailable("The VM is shutting down."));
+        }
+
         let http_req = req.into_inner();
         let read_conn_id = http_req
             .request
-            .ok_or_else(|| Status::unknown("request was expected to be non-empty"))?
+            .ok_or_else(|| Status::invalid_argument("request was expected to be non-empty"))?
             .body;
         let write_conn_id = http_req
             .response_writer
-            .ok_or_else(|| Status::unknown("response_writer was expected to be non-empty"))?
+            .ok_or_else(|| {
+                Status::invalid_argument("response_writer was expected to be non-empty")
+            })?
             .id;
 
         log::info!(
@@ -207,7 +218,7 @@ impl ghttp::http_server::Http for GHttpServer {
             .read(ReadRequest {
                 // Should be isize::MAX, but the length field is i32, so we'll take the max allowable length
                 // https://doc.rust-lang.org/stable/reference/types/numeric.html#machine-dependent-integer-types
-                length: std::i32::MAX,
+                length: i32::MAX,
             })
             .await?
             .into_inner();
@@ -0,0 +1,686 @@
+//NOTE: I really don't understand protobufs. This code is clunky and I appreciate fixes/PRs.
+// I've had a distaste for RPC since CORBA and SOAP didn't make it better.
+
+// Serves any ChainVm to avalanchego as an rpcchainvm plugin.
+// This is the code that we must meet: https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm_client.go
+
+use crate::chainvm::{get_ancestors, Block, ChainVm, Handlers, Host};
+use crate::context::Context;
+use crate::error::{into_status, LandslideError};
+use crate::id::Id;
+use crate::proto::appsender::app_sender_client::AppSenderClient;
+use crate::proto::galiasreader::alias_reader_client::AliasReaderClient;
+use crate::proto::gkeystore::keystore_client::KeystoreClient;
+use crate::proto::gsharedmemory::shared_memory_client::SharedMemoryClient;
+use crate::proto::gsubnetlookup::subnet_lookup_client::SubnetLookupClient;
+use crate::proto::messenger::messenger_client::MessengerClient;
+use crate::proto::rpcdb::database_client::DatabaseClient;
+use crate::proto::vm_proto::vm_server::{Vm, VmServer};
+use crate::proto::vm_proto::*;
+use crate::proto::{GHttpServer, Lock};
+use crate::timestamp::Timestamp;
+use anyhow::Context as AnyhowContext;
+use grr_plugin::{GRpcBroker, HandshakeConfig, Server, ServiceId};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+use tonic::{Request, Response, Status};
+
+//https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm.go#L19
+const AVALANCHE_VM_PROTOCOL_VERSION: u32 = 9;
+
+// https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm.go#L20
+const MAGIC_COOKIE_KEY: &str = "VM_PLUGIN";
+const MAGIC_COOKIE_VALUE: &str = "dynamic";
+
+// Runs vm as a go-plugin of avalanchego, until avalanchego shuts the plugin down.
+pub async fn serve<V: ChainVm>(vm: V) -> Result<(), LandslideError> {
+    log::info!("creating grr-plugin (go-plugin) Server...");
+    let mut plugin = Server::new(
+        AVALANCHE_VM_PROTOCOL_VERSION,
+        HandshakeConfig {
+            magic_cookie_key: MAGIC_COOKIE_KEY.to_string(),
+            magic_cookie_value: MAGIC_COOKIE_VALUE.to_string(),
+        },
+    ).with_context(|| format!("Error creating the plugin server with Avalanche protocol version {} and Handshake configuration with magic_cookie_key: {} and magic_cookie_value: {}", AVALANCHE_VM_PROTOCOL_VERSION, MAGIC_COOKIE_KEY, MAGIC_COOKIE_VALUE))?;
+
+    // extract the JSON-RPC Broker
+    let grpc_broker = Arc::new(Mutex::new(plugin.grpc_broker().await?));
+
+    let vm = VmServer::new(ChainVmServer::new(grpc_broker, vm));
+    log::info!("ChainVm Service Created");
+
+    plugin
+        .serve(vm)
+        .await
+        .context("Error serving the plugin vm using the grr-plugin scaffolding server")?;
+
+    Ok(())
+}
+
+// The gRPC Vm trait only ever references self immutably, so the ChainVm
+// (which is mutated by most calls) is kept behind a lock.
+pub struct ChainVmServer<V: ChainVm> {
+    grpc_broker: Arc<Mutex<GRpcBroker>>,
+    vm: Arc<RwLock<V>>,
+    // Shared with the VM and every JSON-RPC server, and cancelled on shutdown
+    shutdown: CancellationToken,
+}
+
+impl<V: ChainVm> ChainVmServer<V> {
+    pub fn new(grpc_broker: Arc<Mutex<GRpcBroker>>, vm: V) -> ChainVmServer<V> {
+        ChainVmServer {
+            grpc_broker,
+            vm: Arc::new(RwLock::new(vm)),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    async fn open_connection(
+        &self,
+        service_id: ServiceId,
+        target: &str,
+    ) -> Result<Channel, Status> {
+        log::trace!(
+            "opening a new connection to host for service_id: {}",
+            service_id
+        );
+        self.grpc_broker
+            .lock()
+            .await
+            .dial_to_host_service(service_id)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to dial a connection to the {} server {}",
+                    target, service_id,
+                )
+            })
+            .map_err(|e| e.into())
+            .map_err(into_status)
+    }
+
+    // Serves each JSON-RPC handler over its own GHttp server, through the grpc broker.
+    async fn serve_handlers(&self, handlers: Handlers) -> Result<Vec<Handler>, Status> {
+        let mut served = Vec::with_capacity(handlers.len());
+        for (prefix, io_handler) in handlers {
+            let ghttp_server = GHttpServer::new_server(
+                self.grpc_broker.clone(),
+                io_handler,
+                self.shutdown.clone(),
+            );
+
+            log::info!(
+                "Creating a new JSON-RPC 2.0 server for prefix {:?}...",
+                prefix
+            );
+            let server_id = self
+                .grpc_broker
+                .lock()
+                .await
+                .new_grpc_server(ghttp_server)
+                .await
+                .context("Unable to create a new GHttp Server server for handlers")
+                .map_err(|e| e.into())
+                .map_err(into_status)?;
+            log::info!(
+                "Created a new JSON-RPC 2.0 server for prefix {:?} with server_id: {}",
+                prefix,
+                server_id
+            );
+
+            served.push(Handler {
+                prefix,
+                // The handlers take the VM's lock themselves
+                lock_options: Lock::NoLock as u32,
+                server: server_id,
+            });
+        }
+
+        Ok(served)
+    }
+}
+
+#[tonic::async_trait]
+impl<V: ChainVm> Vm for ChainVmServer<V> {
+    async fn initialize(
+        &self,
+        request: Request<InitializeRequest>,
+    ) -> Result<Response<InitializeResponse>, Status> {
+        log::trace!("initialize called");
+        let ir = request.into_inner();
+        log::info!("Full Request: {:?}", ir,);
+
+        let ctx = Context {
+            network_id: ir.network_id,
+            subnet_id: ir.subnet_id,
+            chain_id: ir.chain_id,
+            node_id: ir.node_id,
+
+            x_chain_id: ir.x_chain_id,
+            avax_asset_id: ir.avax_asset_id,
+        };
+        log::trace!("setup context from genesis data");
+
+        let mut versioned_dbs: BTreeMap<Version, DatabaseClient<Channel>> = BTreeMap::new();
+        for db_server in ir.db_servers.iter() {
+            let ver_without_v = db_server.version.trim_start_matches('v');
+            let version = Version::parse(ver_without_v)
+                .with_context(|| format!("In initialize, failed to parse the semver::Version for a VersionedDatabase obtained from the host/client. Version provided by server: {}, with the leading 'v' removed: {}", db_server.version, ver_without_v))
+                .map_err(|e| e.into())
+                .map_err(invalid_argument("database version"))?;
+
+            let conn = self
+                .open_connection(db_server.db_server, "VersionedDatabase")
+                .await?;
+
+            versioned_dbs.insert(version, DatabaseClient::new(conn));
+            log::info!(
+                "initialized versioned db client for server: {:?}",
+                db_server
+            );
+        }
+        log::trace!("initialized all versioned db clients",);
+
+        let db = versioned_dbs.values().next_back().cloned().ok_or_else(|| {
+            Status::invalid_argument("zero versioned_db_clients were found. Unable to proceed without a versioned database.")
+        })?;
+
+        let engine = MessengerClient::new(
+            self.open_connection(ir.engine_server, "engine_server")
+                .await?,
+        );
+        log::trace!("initialized messenger (engine server) client",);
+
+        let keystore = KeystoreClient::new(
+            self.open_connection(ir.keystore_server, "keystore_server")
+                .await?,
+        );
+        log::trace!("initialized keystore client");
+
+        let shared_memory = SharedMemoryClient::new(
+            self.open_connection(ir.shared_memory_server, "shared_memory_server")
+                .await?,
+        );
+        log::trace!("initialized shared memory client",);
+
+        let bc_lookup = AliasReaderClient::new(
+            self.open_connection(ir.bc_lookup_server, "bc_lookup_server")
+                .await?,
+        );
+        log::trace!("initialized alias reader client");
+
+        let sn_lookup = SubnetLookupClient::new(
+            self.open_connection(ir.sn_lookup_server, "sn_lookup_server")
+                .await?,
+        );
+        log::trace!("initialized subnet lookup client",);
+
+        let app_sender = AppSenderClient::new(
+            self.open_connection(ir.app_sender_server, "app_sender_server")
+                .await?,
+        );
+        log::trace!("initialized app sender client");
+
+        let host = Host {
+            ctx,
+            genesis_bytes: ir.genesis_bytes,
+            upgrade_bytes: ir.upgrade_bytes,
+            config_bytes: ir.config_bytes,
+            db,
+            versioned_dbs,
+            engine,
+            keystore,
+            shared_memory,
+            bc_lookup,
+            sn_lookup,
+            app_sender,
+            shutdown: self.shutdown.clone(),
+        };
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm.initialize(host).await.map_err(into_status)?;
+
+        let labid = writable_vm.last_accepted().await.map_err(into_status)?;
+        log::trace!("initialize obtained last accepted block id: {}", labid);
+
+        let block = writable_vm
+            .get_block(&labid)
+            .await
+            .map_err(into_status)?
+            .ok_or_else(|| Status::internal(format!("The last accepted block with Id {} was not found, after the VM was initialized.", labid)))?;
+
+        Ok(Response::new(InitializeResponse {
+            last_accepted_id: labid.to_vec(),
+            last_accepted_parent_id: block.parent_id().to_vec(),
+            bytes: block.bytes().map_err(into_status)?,
+            height: block.height(),
+            timestamp: block.timestamp().bytes().to_vec(),
+            status: block.status() as u32,
+        }))
+    }
+
+    async fn bootstrapping(&self, _request: Request<()>) -> Result<Response<()>, Status> {
+        log::trace!("bootstrapping called");
+        let mut writable_vm = self.vm.write().await;
+        writable_vm.bootstrapping().await.map_err(into_status)?;
+        Ok(Response::new(()))
+    }
+
+    async fn bootstrapped(&self, _request: Request<()>) -> Result<Response<()>, Status> {
+        log::trace!("bootstrapped called");
+        let mut writable_vm = self.vm.write().await;
+        writable_vm.bootstrapped().await.map_err(into_status)?;
+        Ok(Response::new(()))
+    }
+
+    async fn shutdown(&self, _request: Request<()>) -> Result<Response<()>, Status> {
+        log::trace!("shutdown called");
+        // Stops the JSON-RPC servers and the VM's own work right away,
+        // without waiting for whatever holds the VM's lock
+        self.shutdown.cancel();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm.shutdown().await.map_err(into_status)?;
+        Ok(Response::new(()))
+    }
+
+    async fn create_handlers(
+        &self,
+        _request: Request<()>,
+    ) -> Result<Response<CreateHandlersResponse>, Status> {
+        log::info!("create_handlers called");
+        let handlers = V::create_handlers(self.vm.clone())
+            .await
+            .map_err(into_status)?;
+
+        log::info!("responding with API handler services.",);
+        Ok(Response::new(CreateHandlersResponse {
+            handlers: self.serve_handlers(handlers).await?,
+        }))
+    }
+
+    async fn create_static_handlers(
+        &self,
+        _request: Request<()>,
+    ) -> Result<Response<CreateStaticHandlersResponse>, Status> {
+        log::info!("create_static_handlers called");
+        let handlers = self
+            .vm
+            .read()
+            .await
+            .create_static_handlers()
+            .await
+            .map_err(into_status)?;
+
+        log::info!("responding with static API handler services.",);
+        Ok(Response::new(CreateStaticHandlersResponse {
+            handlers: self.serve_handlers(handlers).await?,
+        }))
+    }
+
+    async fn connected(&self, request: Request<ConnectedRequest>) -> Result<Response<()>, Status> {
+        log::trace!("connected called");
+        let cr = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .connected(&cr.node_id, &cr.version)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn disconnected(
+        &self,
+        request: Request<DisconnectedRequest>,
+    ) -> Result<Response<()>, Status> {
+        log::trace!("disconnected called");
+        let dr = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .disconnected(&dr.node_id)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn build_block(
+        &self,
+        _request: Request<()>,
+    ) -> Result<Response<BuildBlockResponse>, Status> {
+        log::trace!("build_block called");
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm.build_block().await.map_err(into_status)?;
+
+        Ok(Response::new(BuildBlockResponse {
+            id: block.id().map_err(into_status)?.to_vec(),
+            parent_id: block.parent_id().to_vec(),
+            bytes: block.bytes().map_err(into_status)?,
+            height: block.height(),
+            timestamp: block.timestamp().bytes().to_vec(),
+        }))
+    }
+
+    async fn parse_block(
+        &self,
+        request: Request<ParseBlockRequest>,
+    ) -> Result<Response<ParseBlockResponse>, Status> {
+        log::trace!("parse_block called");
+        let pbr = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm
+            .parse_block(pbr.bytes.as_ref())
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(
+            parse_block_response(block).map_err(into_status)?,
+        ))
+    }
+
+    async fn get_block(
+        &self,
+        request: Request<GetBlockRequest>,
+    ) -> Result<Response<GetBlockResponse>, Status> {
+        log::trace!("get_block called");
+        let gbr = request.into_inner();
+
+        let block_id = Id::from_slice(&gbr.id).map_err(into_status)?;
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm
+            .get_block(&block_id)
+            .await
+            .map_err(into_status)?
+            // NotFound is what the host expects for a missing block (as opposed to an internal error)
+            .ok_or_else(|| into_status(LandslideError::BlockNotFound { block_id }))?;
+
+        Ok(Response::new(GetBlockResponse {
+            parent_id: block.parent_id().to_vec(),
+            bytes: block.bytes().map_err(into_status)?,
+            status: block.status() as u32,
+            height: block.height(),
+            timestamp: block.timestamp().bytes().to_vec(),
+        }))
+    }
+
+    async fn set_preference(
+        &self,
+        request: Request<SetPreferenceRequest>,
+    ) -> Result<Response<()>, Status> {
+        log::trace!("set_preference called");
+        let spr = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .set_preference(Id::from_slice(&spr.id).map_err(into_status)?)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn health(&self, _request: Request<()>) -> Result<Response<HealthResponse>, Status> {
+        log::trace!("health called");
+        let mut writable_vm = self.vm.write().await;
+        let details = writable_vm.health_check().await.map_err(into_status)?;
+
+        Ok(Response::new(HealthResponse { details }))
+    }
+
+    async fn version(&self, _request: Request<()>) -> Result<Response<VersionResponse>, Status> {
+        log::trace!("version called");
+        let version = self.vm.read().await.version().to_string();
+        log::info!("responding with version {}", version);
+
+        Ok(Response::new(VersionResponse { version }))
+    }
+
+    async fn app_request(&self, request: Request<AppRequestMsg>) -> Result<Response<()>, Status> {
+        log::trace!("app_request called");
+        let arm = request.into_inner();
+
+        let deadline = Timestamp::from_bytes(arm.deadline).map_err(invalid_argument("deadline"))?;
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .app_request(
+                &arm.node_id,
+                arm.request_id,
+                *deadline.offsetdatetime(),
+                &arm.request,
+            )
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn app_request_failed(
+        &self,
+        request: Request<AppRequestFailedMsg>,
+    ) -> Result<Response<()>, Status> {
+        log::trace!("app_request_failed called");
+        let arfm = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .app_request_failed(&arfm.node_id, arfm.request_id)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn app_response(&self, request: Request<AppResponseMsg>) -> Result<Response<()>, Status> {
+        log::trace!("app_response called");
+        let arm = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .app_response(&arm.node_id, arm.request_id, &arm.response)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn app_gossip(&self, request: Request<AppGossipMsg>) -> Result<Response<()>, Status> {
+        log::trace!("app_gossip called");
+        let agm = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        writable_vm
+            .app_gossip(&agm.node_id, &agm.msg)
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn gather(&self, _request: Request<()>) -> Result<Response<GatherResponse>, Status> {
+        log::trace!("gather called");
+        let readable_vm = self.vm.read().await;
+
+        Ok(Response::new(GatherResponse {
+            metric_families: readable_vm
+                .metrics_registry()
+                .map(crate::metrics::gather)
+                .unwrap_or_default(),
+        }))
+    }
+
+    // Copied from: https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm_server.go
+    // The block is parsed from its bytes before it is verified.
+    async fn block_verify(
+        &self,
+        request: Request<BlockVerifyRequest>,
+    ) -> Result<Response<BlockVerifyResponse>, Status> {
+        log::trace!("block_verify called");
+        let bvr = request.into_inner();
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm
+            .parse_block(bvr.bytes.as_ref())
+            .await
+            .map_err(into_status)?;
+        let timestamp = block.timestamp().bytes().to_vec();
+
+        // A decided block was verified before it was decided, and must stay decided
+        if block.status().decided() {
+            return Ok(Response::new(BlockVerifyResponse { timestamp }));
+        }
+
+        writable_vm.verify_block(block).await.map_err(into_status)?;
+
+        Ok(Response::new(BlockVerifyResponse { timestamp }))
+    }
+
+    async fn block_accept(
+        &self,
+        request: Request<BlockAcceptRequest>,
+    ) -> Result<Response<()>, Status> {
+        log::trace!("block_accept called");
+        let bar = request.into_inner();
+
+        let block_id = Id::from_slice(&bar.id).map_err(into_status)?;
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm
+            .get_block(&block_id)
+            .await
+            .map_err(into_status)?
+            .ok_or_else(|| {
+                into_status(LandslideError::BlockNotFound {
+                    block_id: block_id.clone(),
+                })
+            })?;
+
+        writable_vm.accept_block(block).await.map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn block_reject(
+        &self,
+        request: Request<BlockRejectRequest>,
+    ) -> Result<Response<()>, Status> {
+        log::trace!("block_reject called");
+        let brr = request.into_inner();
+
+        let block_id = Id::from_slice(&brr.id).map_err(into_status)?;
+
+        let mut writable_vm = self.vm.write().await;
+        let block = writable_vm
+            .get_block(&block_id)
+            .await
+            .map_err(into_status)?
+            .ok_or_else(|| {
+                into_status(LandslideError::BlockNotFound {
+                    block_id: block_id.clone(),
+                })
+            })?;
+
+        writable_vm.reject_block(block).await.map_err(into_status)?;
+
+        Ok(Response::new(()))
+    }
+
+    async fn get_ancestors(
+        &self,
+        request: Request<GetAncestorsRequest>,
+    ) -> Result<Response<GetAncestorsResponse>, Status> {
+        log::trace!("get_ancestors called");
+        let gar = request.into_inner();
+
+        let block_id = Id::from_slice(&gar.blk_id).map_err(into_status)?;
+        // negative limits are treated as zero
+        let max_blocks_num = usize::try_from(gar.max_blocks_num).unwrap_or(0);
+        let max_blocks_size = usize::try_from(gar.max_blocks_size).unwrap_or(0);
+        // the retrieval time is a golang time.Duration, i.e. nanoseconds
+        let max_retrieval_time =
+            Duration::from_nanos(u64::try_from(gar.max_blocks_retrival_time).unwrap_or(0));
+
+        let mut writable_vm = self.vm.write().await;
+        let blks_bytes = get_ancestors(
+            &mut *writable_vm,
+            &block_id,
+            max_blocks_num,
+            max_blocks_size,
+            max_retrieval_time,
+        )
+        .await
+        .map_err(into_status)?;
+
+        Ok(Response::new(GetAncestorsResponse { blks_bytes }))
+    }
+
+    async fn batched_parse_block(
+        &self,
+        request: Request<BatchedParseBlockRequest>,
+    ) -> Result<Response<BatchedParseBlockResponse>, Status> {
+        log::trace!("batched_parse_block called");
+        let bpbr = request.into_inner();
+
+        // Take the lock once for the whole batch, rather than once per block
+        let mut writable_vm = self.vm.write().await;
+
+        let mut response = Vec::with_capacity(bpbr.request.len());
+        for (index, bytes) in bpbr.request.iter().enumerate() {
+            let parsed = writable_vm
+                .parse_block(bytes.as_ref())
+                .await
+                .and_then(parse_block_response);
+
+            // There is no per-item error in the response, so fail the batch and
+            // tell the host exactly which block could not be parsed.
+            let parse_block_response = parsed.map_err(|err| {
+                let status = into_status(err);
+                Status::with_metadata(
+                    status.code(),
+                    format!(
+                        "Failed to parse block at index {} of {} in the batch: {}",
+                        index,
+                        bpbr.request.len(),
+                        status.message()
+                    ),
+                    status.metadata().clone(),
+                )
+            })?;
+
+            response.push(parse_block_response);
+        }
+
+        Ok(Response::new(BatchedParseBlockResponse { response }))
+    }
+}
+
+// Whatever fails to read from what the host sent is the host's to fix
+fn invalid_argument(argument: &'static str) -> impl FnOnce(LandslideError) -> Status {
+    move |err| {
+        into_status(LandslideError::InvalidArgument {
+            argument,
+            source: Box::new(err),
+        })
+    }
+}
+
+fn parse_block_response<B: Block>(block: B) -> Result<ParseBlockResponse, LandslideError> {
+    Ok(ParseBlockResponse {
+        id: block.id()?.to_vec(),
+        parent_id: block.parent_id().to_vec(),
+        status: block.status() as u32,
+        height: block.height(),
+        timestamp: block.timestamp().bytes().to_vec(),
+    })
+}
@@ -0,0 +1,152 @@
+// Per-chain configuration, from the config bytes avalanchego hands to initialize
+// (i.e. the chain's config.json). Keys are kebab-case, like avalanchego's own chain configs.
+// Every key is optional, and unknown keys are rejected, so typos don't go unnoticed.
+//
+// e.g. {"mempool-max-size": 1024, "log-level": "debug", "gossip-enabled": false}
+use super::gossip::{DEFAULT_GOSSIP_CACHE_SIZE, DEFAULT_GOSSIP_MAX_PER_SECOND};
+use super::mempool::DEFAULT_MEMPOOL_MAX_SIZE;
+use anyhow::anyhow;
+use landslide::error::LandslideError;
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use time::Duration;
+
+// How far ahead of this node's time a block's timestamp may be, unless configured otherwise
+pub const DEFAULT_MAX_FUTURE_BLOCK_TIME_SECS: u64 = 60 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    // How many proposals may wait to be built
+    pub mempool_max_size: usize,
+
+    pub max_future_block_time_secs: u64,
+
+    // One of off, error, warn, info, debug or trace. Leaves the logger as it is when unset.
+    // With LANDSLIDE_LOG_CONFIG_FILE set, it can only lower the levels in that file, not raise them.
+    pub log_level: Option<String>,
+
+    // Whether the chain's JSON-RPC API is served at all...
+    pub api_enabled: bool,
+    // ...and whether it includes the peers this node is connected to, and asking them for blocks
+    pub peers_api_enabled: bool,
+
+    // Whether proposals are gossiped to peers. Gossip from peers is accepted either way.
+    pub gossip_enabled: bool,
+    pub gossip_cache_size: usize,
+    pub gossip_max_per_second: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mempool_max_size: DEFAULT_MEMPOOL_MAX_SIZE,
+            max_future_block_time_secs: DEFAULT_MAX_FUTURE_BLOCK_TIME_SECS,
+            log_level: None,
+            api_enabled: true,
+            peers_api_enabled: true,
+            gossip_enabled: true,
+            gossip_cache_size: DEFAULT_GOSSIP_CACHE_SIZE,
+            gossip_max_per_second: DEFAULT_GOSSIP_MAX_PER_SECOND,
+        }
+    }
+}
+
+impl Config {
+    // No config bytes at all means every default
+    pub fn parse(config_bytes: &[u8]) -> Result<Config, LandslideError> {
+        if config_bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Config::default());
+        }
+
+        let config: Config = serde_json::from_slice(config_bytes).map_err(|err| {
+            LandslideError::Config(anyhow!("Unable to parse the chain's config: {}", err))
+        })?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), LandslideError> {
+        if self.mempool_max_size == 0 {
+            return Err(LandslideError::Config(anyhow!(
+                "mempool-max-size must be at least 1"
+            )));
+        }
+
+        if self.gossip_cache_size == 0 {
+            return Err(LandslideError::Config(anyhow!(
+                "gossip-cache-size must be at least 1"
+            )));
+        }
+
+        // time::Duration counts seconds in an i64
+        if i64::try_from(self.max_future_block_time_secs).is_err() {
+            return Err(LandslideError::Config(anyhow!(
+                "max-future-block-time-secs must be at most {}",
+                i64::MAX
+            )));
+        }
+
+        self.log_level_filter()?;
+
+        Ok(())
+    }
+
+    pub fn max_future_block_time(&self) -> Duration {
+        Duration::seconds(self.max_future_block_time_secs as i64)
+    }
+
+    pub fn log_level_filter(&self) -> Result<Option<LevelFilter>, LandslideError> {
+        self.log_level
+            .as_ref()
+            .map(|level| {
+                LevelFilter::from_str(level).map_err(|_| {
+                    LandslideError::Config(anyhow!(
+                        "log-level must be one of off, error, warn, info, debug or trace, not {}",
+                        level
+                    ))
+                })
+            })
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(Config::parse(b"").unwrap(), Config::default());
+        assert_eq!(Config::parse(b"{}").unwrap(), Config::default());
+        assert_eq!(
+            Config::default().max_future_block_time(),
+            Duration::hours(1)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            br#"{"mempool-max-size": 10, "log-level": "debug", "gossip-enabled": false}"#,
+        )
+        .unwrap();
+        assert_eq!(config.mempool_max_size, 10);
+        assert_eq!(config.log_level_filter().unwrap(), Some(LevelFilter::Debug));
+        assert!(!config.gossip_enabled);
+        assert!(config.api_enabled);
+    }
+
+    #[test]
+    fn test_rejects_invalid() {
+        let err = Config::parse(br#"{"mempool-size": 10}"#).unwrap_err();
+        assert!(err.to_string().contains("unknown field `mempool-size`"));
+
+        assert!(Config::parse(br#"{"mempool-max-size": 0}"#).is_err());
+        assert!(Config::parse(br#"{"log-level": "loud"}"#).is_err());
+        assert!(Config::parse(br#"{"api-enabled": "yes"}"#).is_err());
+        assert!(Config::parse(b"not json").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+// Gossips proposed block data to peers, so whichever validator builds the next block has it.
+// Adapted from the mempool gossip in: https://github.com/ava-labs/avalanchego/blob/master/vms/platformvm/mempool_gossip.go
+
+use super::state::BLOCK_DATA_LEN;
+use landslide::codec::{Packer, Unpacker, CODEC_VERSION};
+use landslide::error::LandslideError;
+use landslide::id::Id;
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+// How many recently seen pieces of data are remembered, to not gossip them again
+pub const DEFAULT_GOSSIP_CACHE_SIZE: usize = 8192;
+
+// How many gossip messages may be sent per second, on average...
+pub const DEFAULT_GOSSIP_MAX_PER_SECOND: u32 = 16;
+// ...and in a single burst
+const GOSSIP_BURST: u32 = 32;
+
+// A gossip message carries a single proposal:
+//     [codec version: u16] [data: 32 bytes]
+pub fn encode(data: &[u8; BLOCK_DATA_LEN]) -> Result<Vec<u8>, LandslideError> {
+    let mut packer = Packer::with_version(CODEC_VERSION)?;
+    packer.pack_fixed_bytes(data)?;
+    Ok(packer.into_bytes())
+}
+
+pub fn decode(msg: &[u8]) -> Result<[u8; BLOCK_DATA_LEN], LandslideError> {
+    let mut unpacker = Unpacker::with_version(msg, CODEC_VERSION)?;
+    let data = unpacker.unpack_fixed_bytes(BLOCK_DATA_LEN)?.try_into()?;
+    unpacker.done()?;
+    Ok(data)
+}
+
+// Decides what gets gossiped, and when.
+// Data is only gossiped the first time it is seen (whether proposed here or received
+// from a peer), which stops it from echoing around the network. Sending is rate limited
+// by a token bucket, so a flood of proposals can't flood peers in turn.
+#[derive(Debug)]
+pub struct Gossiper {
+    cache_size: usize,
+    // oldest first, so the oldest can be forgotten once the cache is full
+    recent: VecDeque<Id>,
+    recent_set: HashSet<Id>,
+
+    max_per_second: u32,
+    tokens: u32,
+    last_refill: Instant,
+}
+
+impl Gossiper {
+    pub fn new(cache_size: usize, max_per_second: u32) -> Gossiper {
+        Gossiper {
+            cache_size,
+            recent: VecDeque::new(),
+            recent_set: HashSet::new(),
+
+            max_per_second,
+            tokens: GOSSIP_BURST,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // Remembers data as seen. Returns false when it was already seen recently.
+    pub fn observe(&mut self, data: &[u8]) -> bool {
+        let id = Id::generate(data);
+        if !self.recent_set.insert(id.clone()) {
+            return false;
+        }
+
+        self.recent.push_back(id);
+        if self.recent.len() > self.cache_size {
+            if let Some(oldest) = self.recent.pop_front() {
+                self.recent_set.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    // Whether a gossip message may be sent right now. Uses up a token if so.
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&mut self, now: Instant) -> bool {
+        if self.max_per_second > 0 {
+            let refill_every = Duration::from_secs(1) / self.max_per_second;
+            let elapsed = now.saturating_duration_since(self.last_refill);
+            let refilled = (elapsed.as_nanos() / refill_every.as_nanos()) as u32;
+            if refilled > 0 {
+                self.tokens = self.tokens.saturating_add(refilled).min(GOSSIP_BURST);
+                self.last_refill += refill_every * refilled;
+            }
+        }
+
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let data = [9; BLOCK_DATA_LEN];
+        let msg = encode(&data).unwrap();
+        assert_eq!(msg.len(), 2 + BLOCK_DATA_LEN);
+        assert_eq!(decode(&msg).unwrap(), data);
+
+        assert!(decode(&msg[..msg.len() - 1]).is_err());
+        assert!(decode(&[msg.clone(), vec![0]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_observe_dedups_within_cache() {
+        let mut gossiper = Gossiper::new(2, DEFAULT_GOSSIP_MAX_PER_SECOND);
+        assert!(gossiper.observe(&[1; BLOCK_DATA_LEN]));
+        assert!(!gossiper.observe(&[1; BLOCK_DATA_LEN]));
+
+        assert!(gossiper.observe(&[2; BLOCK_DATA_LEN]));
+        assert!(gossiper.observe(&[3; BLOCK_DATA_LEN]));
+        // forgotten, as the oldest once the cache overflowed
+        assert!(gossiper.observe(&[1; BLOCK_DATA_LEN]));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut gossiper = Gossiper::new(DEFAULT_GOSSIP_CACHE_SIZE, 10);
+        let start = gossiper.last_refill;
+        for _ in 0..GOSSIP_BURST {
+            assert!(gossiper.allow_at(start));
+        }
+        assert!(!gossiper.allow_at(start));
+
+        // one token comes back every 100ms
+        assert!(!gossiper.allow_at(start + Duration::from_millis(99)));
+        assert!(gossiper.allow_at(start + Duration::from_millis(100)));
+        assert!(!gossiper.allow_at(start + Duration::from_millis(150)));
+        assert!(gossiper.allow_at(start + Duration::from_millis(250)));
+    }
+}
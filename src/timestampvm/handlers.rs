@@ -1,17 +1,20 @@
-use super::state::BLOCK_DATA_LEN;
-use super::TimestampVmInterior;
-use crate::encoding::{Checksum, Encoding};
-use crate::error::into_jsonrpc_error;
-use crate::id::Id;
+use super::metrics::Metrics;
+use super::state::{Block, BLOCK_DATA_LEN};
+use super::TimestampVm;
 use jsonrpc_core::{BoxFuture, Error as JsonRpcError, IoHandler, Result};
 use jsonrpc_derive::rpc;
+use landslide::chainvm::Lifecycle;
+use landslide::encoding::{Checksum, Encoding};
+use landslide::error::into_jsonrpc_error;
+use landslide::id::Id;
+use landslide::kvstore::KeyValueStore;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-pub fn new(vm: Arc<RwLock<TimestampVmInterior>>) -> IoHandler {
+pub fn new<S: KeyValueStore>(vm: Arc<RwLock<TimestampVm<S>>>, metrics: Metrics) -> IoHandler {
     let mut io = IoHandler::new();
-    let handlers = HandlersImpl { vm };
+    let handlers = HandlersImpl { vm, metrics };
 
     io.extend_with(handlers.to_delegate());
 
@@ -33,6 +36,11 @@ pub struct GetBlockArgs {
     id: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct GetBlockByHeightArgs {
+    height: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GetBlockReply {
     timestamp: u64,
@@ -42,6 +50,44 @@ pub struct GetBlockReply {
     parent_id: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct PeerReply {
+    #[serde(rename = "nodeID")]
+    node_id: String,
+    version: String,
+    // unix timestamps, in seconds
+    #[serde(rename = "connectedAt")]
+    connected_at: i64,
+    #[serde(rename = "lastMessageAt")]
+    last_message_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PeersReply {
+    peers: Vec<PeerReply>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PeerHasBlockArgs {
+    // The next connected peer is asked when unset
+    #[serde(rename = "nodeID")]
+    node_id: Option<String>,
+    id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PeerHasBlockReply {
+    #[serde(rename = "nodeID")]
+    node_id: String,
+    #[serde(rename = "hasBlock")]
+    has_block: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LifecycleReply {
+    lifecycle: Lifecycle,
+}
+
 #[rpc(server)]
 pub trait Handlers {
     #[rpc(name = "proposeBlock", alias("timestampvm.proposeBlock"))]
@@ -49,15 +95,32 @@ pub trait Handlers {
 
     #[rpc(name = "getBlock", alias("timestampvm.getBlock"))]
     fn get_block(&self, args: GetBlockArgs) -> BoxFuture<Result<GetBlockReply>>;
+
+    #[rpc(name = "getBlockByHeight", alias("timestampvm.getBlockByHeight"))]
+    fn get_block_by_height(&self, args: GetBlockByHeightArgs) -> BoxFuture<Result<GetBlockReply>>;
+
+    #[rpc(name = "peers", alias("timestampvm.peers"))]
+    fn peers(&self) -> BoxFuture<Result<PeersReply>>;
+
+    #[rpc(name = "peerHasBlock", alias("timestampvm.peerHasBlock"))]
+    fn peer_has_block(&self, args: PeerHasBlockArgs) -> BoxFuture<Result<PeerHasBlockReply>>;
+
+    #[rpc(name = "lifecycle", alias("timestampvm.lifecycle"))]
+    fn lifecycle(&self) -> BoxFuture<Result<LifecycleReply>>;
 }
 
-pub struct HandlersImpl {
-    vm: Arc<RwLock<TimestampVmInterior>>,
+pub struct HandlersImpl<S: KeyValueStore> {
+    vm: Arc<RwLock<TimestampVm<S>>>,
+    metrics: Metrics,
 }
 
-impl Handlers for HandlersImpl {
+impl<S: KeyValueStore> Handlers for HandlersImpl<S> {
     fn propose_block(&self, args: ProposeBlockArgs) -> BoxFuture<Result<ProposeBlockReply>> {
         log::trace!("propose_block called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["proposeBlock"])
+            .inc();
         let vm = self.vm.clone();
 
         Box::pin(async move {
@@ -85,6 +148,10 @@ impl Handlers for HandlersImpl {
 
     fn get_block(&self, args: GetBlockArgs) -> BoxFuture<Result<GetBlockReply>> {
         log::info!("get_block called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["getBlock"])
+            .inc();
         let vm = self.vm.clone();
 
         Box::pin(async move {
@@ -106,36 +173,172 @@ impl Handlers for HandlersImpl {
                 },
             };
 
-            let mut block = mutable_state.get_block(&id).await
+            let block = mutable_state.get_block(&id).await
             .map_err(into_jsonrpc_error)?
             .ok_or_else(||JsonRpcError::invalid_params("Block with the provided id (or last accepted block with id) does not exist."))?;
 
-            let bid = block.generate_id().map_err(into_jsonrpc_error)?.clone();
+            get_block_reply(block)
+        })
+    }
 
-            let encoded_data = Encoding::Cb58
-                .encode(block.data().as_ref(), Checksum::Yes)
-                .map_err(into_jsonrpc_error)?;
+    fn get_block_by_height(&self, args: GetBlockByHeightArgs) -> BoxFuture<Result<GetBlockReply>> {
+        log::info!("get_block_by_height called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["getBlockByHeight"])
+            .inc();
+        let vm = self.vm.clone();
 
-            let timestamp_unix_i64 = block.timestamp().offsetdatetime().unix_timestamp();
+        Box::pin(async move {
+            let mut mutable_vm = vm.write().await;
+            let mutable_state = mutable_vm.mut_state().await.map_err(into_jsonrpc_error)?;
 
-            let timestamp_unix_u64 = u64::try_from(timestamp_unix_i64)
-                .map_err(|e| e.into())
-                .map_err(into_jsonrpc_error)?;
+            let id = mutable_state
+                .get_block_id_at_height(args.height)
+                .await
+                .map_err(into_jsonrpc_error)?
+                .ok_or_else(|| {
+                    JsonRpcError::invalid_params(format!(
+                        "No accepted block exists at height {}.",
+                        args.height
+                    ))
+                })?;
+
+            let block = mutable_state
+                .get_block(&id)
+                .await
+                .map_err(into_jsonrpc_error)?
+                .ok_or_else(|| {
+                    JsonRpcError::invalid_params(format!(
+                        "Block with id {} indexed at height {} does not exist.",
+                        id, args.height
+                    ))
+                })?;
+
+            get_block_reply(block)
+        })
+    }
+
+    fn peers(&self) -> BoxFuture<Result<PeersReply>> {
+        log::trace!("peers called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["peers"])
+            .inc();
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            let vm = vm.read().await;
+            if !vm.config.peers_api_enabled {
+                return Err(JsonRpcError::method_not_found());
+            }
+
+            let mut peers = Vec::new();
+            for (node_id, peer) in vm.peers.iter() {
+                peers.push(PeerReply {
+                    node_id: format_node_id(node_id)?,
+                    version: peer.version.clone(),
+                    connected_at: peer.connected_at.unix_timestamp(),
+                    last_message_at: peer.last_message_at.map(|at| at.unix_timestamp()),
+                });
+            }
+
+            Ok(PeersReply { peers })
+        })
+    }
 
-            let id_str = Encoding::Cb58
-                .encode(bid.as_ref(), Checksum::Yes)
+    // Asks a connected peer whether it has a block, without holding up the VM while it answers
+    fn peer_has_block(&self, args: PeerHasBlockArgs) -> BoxFuture<Result<PeerHasBlockReply>> {
+        log::trace!("peer_has_block called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["peerHasBlock"])
+            .inc();
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            if !vm.read().await.config.peers_api_enabled {
+                return Err(JsonRpcError::method_not_found());
+            }
+
+            let node_id = args
+                .node_id
+                .map(|node_id| parse_node_id(&node_id))
+                .transpose()?;
+            let block_id = Encoding::Cb58
+                .decode(args.id, Checksum::Yes)
                 .map_err(into_jsonrpc_error)?;
+            let block_id = Id::from_slice(block_id.as_ref()).map_err(into_jsonrpc_error)?;
 
-            let parent_id_str = Encoding::Cb58
-                .encode(block.parent_id().as_ref(), Checksum::Yes)
+            let (node_id, has_block) = TimestampVm::ask_has_block(vm, node_id, block_id)
+                .await
                 .map_err(into_jsonrpc_error)?;
 
-            Ok(GetBlockReply {
-                id: id_str,
-                parent_id: parent_id_str,
-                data: encoded_data,
-                timestamp: timestamp_unix_u64,
+            Ok(PeerHasBlockReply {
+                node_id: format_node_id(&node_id)?,
+                has_block,
+            })
+        })
+    }
+
+    fn lifecycle(&self) -> BoxFuture<Result<LifecycleReply>> {
+        log::trace!("lifecycle called");
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["lifecycle"])
+            .inc();
+        let vm = self.vm.clone();
+
+        Box::pin(async move {
+            Ok(LifecycleReply {
+                lifecycle: vm.read().await.lifecycle,
             })
         })
     }
 }
+
+// Node ids are shown the way avalanchego shows them, e.g. NodeID-<cb58>
+fn format_node_id(node_id: &[u8]) -> Result<String> {
+    let encoded = Encoding::Cb58
+        .encode(node_id, Checksum::Yes)
+        .map_err(into_jsonrpc_error)?;
+    Ok(format!("NodeID-{}", encoded))
+}
+
+fn parse_node_id(node_id: &str) -> Result<Vec<u8>> {
+    let encoded = node_id.strip_prefix("NodeID-").ok_or_else(|| {
+        JsonRpcError::invalid_params(format!("Node id {} doesn't start with NodeID-", node_id))
+    })?;
+    Encoding::Cb58
+        .decode(encoded.to_string(), Checksum::Yes)
+        .map_err(into_jsonrpc_error)
+}
+
+fn get_block_reply(mut block: Block) -> Result<GetBlockReply> {
+    let bid = block.generate_id().map_err(into_jsonrpc_error)?.clone();
+
+    let encoded_data = Encoding::Cb58
+        .encode(block.data().as_ref(), Checksum::Yes)
+        .map_err(into_jsonrpc_error)?;
+
+    let timestamp_unix_i64 = block.timestamp().offsetdatetime().unix_timestamp();
+
+    let timestamp_unix_u64 = u64::try_from(timestamp_unix_i64)
+        .map_err(|e| e.into())
+        .map_err(into_jsonrpc_error)?;
+
+    let id_str = Encoding::Cb58
+        .encode(bid.as_ref(), Checksum::Yes)
+        .map_err(into_jsonrpc_error)?;
+
+    let parent_id_str = Encoding::Cb58
+        .encode(block.parent_id().as_ref(), Checksum::Yes)
+        .map_err(into_jsonrpc_error)?;
+
+    Ok(GetBlockReply {
+        id: id_str,
+        parent_id: parent_id_str,
+        data: encoded_data,
+        timestamp: timestamp_unix_u64,
+    })
+}
@@ -0,0 +1,47 @@
+// What Vm::health reports to avalanchego's health API, as JSON.
+// Adapted from: https://github.com/ava-labs/avalanchego/blob/master/vms/platformvm/health.go
+use landslide::chainvm::Lifecycle;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseHealth {
+    pub reachable: bool,
+    pub latency_ms: u128,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MempoolHealth {
+    pub size: usize,
+    pub max_size: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub healthy: bool,
+
+    pub lifecycle: Lifecycle,
+    pub state_initialized: bool,
+    pub database: DatabaseHealth,
+
+    // Seconds since the last accepted block's timestamp. Informational only,
+    // since a chain nobody proposes anything to never gets new blocks.
+    pub last_accepted_block_age_secs: Option<i64>,
+    pub mempool: MempoolHealth,
+    pub connected_peers: usize,
+}
+
+impl HealthReport {
+    // The critical checks: the VM is running (or catching up), and its state is there
+    pub fn check(&mut self) -> bool {
+        self.healthy = matches!(
+            self.lifecycle,
+            Lifecycle::Bootstrapping | Lifecycle::NormalOp
+        ) && self.database.reachable
+            && self.state_initialized;
+        self.healthy
+    }
+}
@@ -0,0 +1,165 @@
+use super::state::BLOCK_DATA_LEN;
+use landslide::error::LandslideError;
+use landslide::id::Id;
+use std::collections::{HashSet, VecDeque};
+
+// How many proposals may wait to be built, unless configured otherwise
+pub const DEFAULT_MEMPOOL_MAX_SIZE: usize = 4096;
+
+// Block data proposed through the API, waiting to be built into blocks.
+// Data is built in the order it was proposed, and identified by the hash of its content.
+// Data stays known (so proposing it again is a duplicate) from when it is added,
+// until a block containing it is accepted, or the block built from it is rejected.
+//
+// The mempool only keeps data in memory; persisting it is up to State.
+// Every entry is persisted under the sequence number add() hands out, so
+// restoring them in sequence order brings back the original order.
+#[derive(Debug)]
+pub struct Mempool {
+    max_size: usize,
+    next_seq: u64,
+
+    // waiting to be built, oldest first
+    pending: VecDeque<[u8; BLOCK_DATA_LEN]>,
+
+    // everything pending, plus everything built into a block not yet decided
+    known: HashSet<Id>,
+}
+
+impl Mempool {
+    pub fn new(max_size: usize) -> Mempool {
+        Mempool {
+            max_size,
+            next_seq: 0,
+            pending: VecDeque::new(),
+            known: HashSet::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // Adds data behind everything already pending, and returns the sequence
+    // number to persist it under.
+    pub fn add(&mut self, data: [u8; BLOCK_DATA_LEN]) -> Result<u64, LandslideError> {
+        let id = Id::generate(&data);
+        if self.known.contains(&id) {
+            return Err(LandslideError::MempoolDuplicate { id });
+        }
+        if self.pending.len() >= self.max_size {
+            return Err(LandslideError::MempoolFull {
+                max_size: self.max_size,
+            });
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.known.insert(id);
+        self.pending.push_back(data);
+
+        Ok(seq)
+    }
+
+    // Brings back an entry persisted before a restart. Entries must be restored
+    // in sequence order, and are restored even when that exceeds max_size.
+    pub fn restore(&mut self, seq: u64, data: [u8; BLOCK_DATA_LEN]) {
+        self.next_seq = self.next_seq.max(seq + 1);
+        if self.known.insert(Id::generate(&data)) {
+            self.pending.push_back(data);
+        }
+    }
+
+    // Takes the oldest pending data, to build a block with.
+    // It stays known until the block is decided.
+    pub fn pop(&mut self) -> Option<[u8; BLOCK_DATA_LEN]> {
+        self.pending.pop_front()
+    }
+
+    // Forgets data that was included in an accepted block (whether built here or not).
+    // Returns whether the data was known at all.
+    pub fn remove(&mut self, data: &[u8]) -> bool {
+        self.pending.retain(|pending| pending[..] != data[..]);
+        self.known.remove(&Id::generate(data))
+    }
+
+    // Puts data back at the front, when the block built from it was rejected.
+    // Data that isn't known any more (e.g. because another block containing it
+    // was accepted in the meantime) is not requeued.
+    pub fn requeue(&mut self, data: [u8; BLOCK_DATA_LEN]) -> bool {
+        let requeue = self.known.contains(&Id::generate(&data)) && !self.pending.contains(&data);
+        if requeue {
+            self.pending.push_front(data);
+        }
+        requeue
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fifo_and_dedup() {
+        let mut mempool = Mempool::new(10);
+        assert_eq!(mempool.add([1; BLOCK_DATA_LEN]).unwrap(), 0);
+        assert_eq!(mempool.add([2; BLOCK_DATA_LEN]).unwrap(), 1);
+        assert!(matches!(
+            mempool.add([1; BLOCK_DATA_LEN]),
+            Err(LandslideError::MempoolDuplicate { .. })
+        ));
+
+        assert_eq!(mempool.pop(), Some([1; BLOCK_DATA_LEN]));
+        // still a duplicate while the block built from it is undecided
+        assert!(mempool.add([1; BLOCK_DATA_LEN]).is_err());
+        assert_eq!(mempool.pop(), Some([2; BLOCK_DATA_LEN]));
+        assert_eq!(mempool.pop(), None);
+    }
+
+    #[test]
+    fn test_max_size() {
+        let mut mempool = Mempool::new(1);
+        mempool.add([1; BLOCK_DATA_LEN]).unwrap();
+        assert!(matches!(
+            mempool.add([2; BLOCK_DATA_LEN]),
+            Err(LandslideError::MempoolFull { max_size: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_remove_and_requeue() {
+        let mut mempool = Mempool::new(10);
+        mempool.add([1; BLOCK_DATA_LEN]).unwrap();
+        mempool.add([2; BLOCK_DATA_LEN]).unwrap();
+        mempool.add([3; BLOCK_DATA_LEN]).unwrap();
+
+        // accepted in a block built elsewhere
+        assert!(mempool.remove(&[2; BLOCK_DATA_LEN]));
+        assert_eq!(mempool.len(), 2);
+
+        let built = mempool.pop().unwrap();
+        assert!(mempool.requeue(built));
+        assert_eq!(mempool.pop(), Some([1; BLOCK_DATA_LEN]));
+
+        // a competing block with the same data was accepted first
+        assert!(mempool.remove(&built));
+        assert!(!mempool.requeue(built));
+        assert_eq!(mempool.pop(), Some([3; BLOCK_DATA_LEN]));
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut mempool = Mempool::new(1);
+        mempool.restore(4, [1; BLOCK_DATA_LEN]);
+        mempool.restore(7, [2; BLOCK_DATA_LEN]);
+        assert_eq!(mempool.len(), 2);
+
+        assert_eq!(mempool.pop(), Some([1; BLOCK_DATA_LEN]));
+        assert_eq!(mempool.pop(), Some([2; BLOCK_DATA_LEN]));
+        assert_eq!(mempool.add([3; BLOCK_DATA_LEN]).unwrap(), 8);
+    }
+}
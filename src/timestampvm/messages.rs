@@ -0,0 +1,48 @@
+// The requests TimestampVm answers for its peers, over landslide::appsender
+use landslide::appsender::{AppMessage, AppRequest};
+use landslide::codec::{Packer, Unpacker};
+use landslide::error::LandslideError;
+use landslide::id::{Id, BYTE_LENGTH};
+
+// Asks a peer whether it has the block with block_id (whether decided or still processing)
+#[derive(Debug, PartialEq)]
+pub struct HasBlockRequest {
+    pub block_id: Id,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct HasBlockResponse {
+    pub has_block: bool,
+}
+
+impl AppMessage for HasBlockRequest {
+    const MESSAGE_TYPE: u16 = 1;
+
+    fn pack(&self, packer: &mut Packer) -> Result<(), LandslideError> {
+        packer.pack_fixed_bytes(self.block_id.as_ref())
+    }
+
+    fn unpack(unpacker: &mut Unpacker) -> Result<Self, LandslideError> {
+        Ok(HasBlockRequest {
+            block_id: Id::from_slice(unpacker.unpack_fixed_bytes(BYTE_LENGTH)?)?,
+        })
+    }
+}
+
+impl AppMessage for HasBlockResponse {
+    const MESSAGE_TYPE: u16 = 2;
+
+    fn pack(&self, packer: &mut Packer) -> Result<(), LandslideError> {
+        packer.pack_byte(self.has_block as u8)
+    }
+
+    fn unpack(unpacker: &mut Unpacker) -> Result<Self, LandslideError> {
+        Ok(HasBlockResponse {
+            has_block: unpacker.unpack_byte()? != 0,
+        })
+    }
+}
+
+impl AppRequest for HasBlockRequest {
+    type Response = HasBlockResponse;
+}
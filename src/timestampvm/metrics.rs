@@ -0,0 +1,100 @@
+use landslide::error::LandslideError;
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+};
+
+// All the metrics the TimestampVm reports to avalanchego through Vm::gather.
+// Every field is a cheap handle onto the same underlying metric, so Metrics
+// can be cloned into whoever needs to record something (State, JSON-RPC handlers, etc.)
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+
+    pub blocks_built: IntCounter,
+    pub blocks_verified: IntCounter,
+    pub blocks_accepted: IntCounter,
+    pub blocks_rejected: IntCounter,
+
+    // labelled by operation: build, verify, accept or reject
+    pub block_operation_duration: HistogramVec,
+
+    pub mempool_size: IntGauge,
+
+    pub connected_peers: IntGauge,
+
+    // labelled by the store call: get, put, delete, write_batch, new_iterator, close
+    pub db_call_duration: HistogramVec,
+
+    // labelled by the JSON-RPC method
+    pub jsonrpc_calls: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Metrics, LandslideError> {
+        let registry = Registry::new();
+
+        let blocks_built = IntCounter::new("blocks_built", "Number of blocks built")?;
+        let blocks_verified = IntCounter::new("blocks_verified", "Number of blocks verified")?;
+        let blocks_accepted = IntCounter::new("blocks_accepted", "Number of blocks accepted")?;
+        let blocks_rejected = IntCounter::new("blocks_rejected", "Number of blocks rejected")?;
+
+        let block_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "block_operation_duration_seconds",
+                "Time taken to build, verify, accept or reject a block",
+            ),
+            &["operation"],
+        )?;
+
+        let mempool_size = IntGauge::new(
+            "mempool_size",
+            "Number of proposed block data waiting to be built into a block",
+        )?;
+
+        let connected_peers = IntGauge::new(
+            "connected_peers",
+            "Number of peers avalanchego reports the VM as connected to",
+        )?;
+
+        let db_call_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "db_call_duration_seconds",
+                "Latency of calls from State to the database",
+            ),
+            &["method"],
+        )?;
+
+        let jsonrpc_calls = IntCounterVec::new(
+            Opts::new("jsonrpc_calls", "Number of calls to each JSON-RPC handler"),
+            &["method"],
+        )?;
+
+        registry.register(Box::new(blocks_built.clone()))?;
+        registry.register(Box::new(blocks_verified.clone()))?;
+        registry.register(Box::new(blocks_accepted.clone()))?;
+        registry.register(Box::new(blocks_rejected.clone()))?;
+        registry.register(Box::new(block_operation_duration.clone()))?;
+        registry.register(Box::new(mempool_size.clone()))?;
+        registry.register(Box::new(connected_peers.clone()))?;
+        registry.register(Box::new(db_call_duration.clone()))?;
+        registry.register(Box::new(jsonrpc_calls.clone()))?;
+
+        Ok(Metrics {
+            registry,
+
+            blocks_built,
+            blocks_verified,
+            blocks_accepted,
+            blocks_rejected,
+            block_operation_duration,
+            mempool_size,
+            connected_peers,
+            db_call_duration,
+            jsonrpc_calls,
+        })
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+}
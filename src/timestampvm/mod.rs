@@ -1,101 +1,174 @@
-//NOTE: I really don't understand protobufs. This code is clunky and I appreciate fixes/PRs.
-// I've had a distaste for RPC since CORBA and SOAP didn't make it better.
+mod config;
+mod gossip;
 mod handlers;
+mod health;
+mod mempool;
+mod messages;
+mod metrics;
+mod peers;
+mod processing;
 mod state;
 mod static_handlers;
-
-use crate::error::LandslideError;
-
-use super::context::Context;
-use super::proto;
-use super::proto::vm_proto::*;
+mod upgrades;
+
+use anyhow::{anyhow, Context as AnyhowContext};
+use config::Config;
+use gossip::Gossiper;
+use health::{DatabaseHealth, HealthReport, MempoolHealth};
+use landslide::appsender::{AppNetwork, REQUEST_TIMEOUT};
+use landslide::chainvm::{ChainVm, Handlers, Host, Lifecycle, Status as BlockStatus};
+use landslide::context::Context;
+use landslide::error::LandslideError;
+use landslide::id::{Id, ROOT_PARENT_ID};
+use landslide::kvstore::{KeyValueStore, RpcDb};
+use landslide::proto::appsender::app_sender_client::AppSenderClient;
+use landslide::proto::appsender::{SendAppGossipMsg, SendAppGossipSpecificMsg};
+use landslide::proto::messenger::messenger_client::MessengerClient;
+use landslide::proto::messenger::NotifyRequest;
+use landslide::proto::rpcdb::database_client::DatabaseClient;
+use landslide::proto::Message;
+use mempool::Mempool;
+use messages::{HasBlockRequest, HasBlockResponse};
+use metrics::Metrics;
+use peers::{Peers, GOSSIP_PEERS};
+use processing::ProcessingBlocks;
+use prometheus::Registry;
 use semver::Version;
-use state::{Block, State, Status as BlockStatus, BLOCK_DATA_LEN};
-use std::collections::BTreeMap;
-use tonic::{Request, Response};
-
-use super::error::into_status;
-use crate::id::{Id, ROOT_PARENT_ID};
-use crate::proto::vm_proto::vm_server::Vm;
-use anyhow::{anyhow, Context as AnyhowContext, Result};
-use grr_plugin::GRpcBroker;
-use grr_plugin::ServiceId;
-use grr_plugin::Status;
-use hyper::{Body, Request as HyperRequest, Response as HyperResponse};
-use std::collections::HashMap;
-use std::error::Error as StdError;
+use state::{Batch, Block, State, BLOCK_DATA_LEN};
 use std::sync::Arc;
+use std::time::Instant;
 use time::{Duration, OffsetDateTime};
-use tokio::sync::Mutex;
 use tokio::sync::RwLock;
-use tonic::body::BoxBody;
+use tokio_util::sync::CancellationToken;
 use tonic::transport::Channel;
-use tonic::transport::NamedService;
-use tower::Service;
+use upgrades::{Fork, Upgrades};
 
-use super::proto::appsender::app_sender_client::*;
-use super::proto::Message;
+// How many heights the height index backfill writes at a time
+const HEIGHT_INDEX_CHUNK_SIZE: u64 = 1024;
 
-use super::proto::rpcdb::database_client::*;
+// Opens the store the VM keeps its state in, given the database avalanchego provides
+type OpenStore<S> = Box<dyn FnOnce(DatabaseClient<Channel>) -> S + Send + Sync>;
 
-use super::proto::messenger::messenger_client::*;
-use super::proto::messenger::NotifyRequest;
+pub struct TimestampVm<S: KeyValueStore = RpcDb> {
+    ctx: Option<Context>,
+    version: Version,
+    lifecycle: Lifecycle,
+    config: Config,
+    upgrades: Upgrades,
 
-use super::proto::gsubnetlookup::subnet_lookup_client::*;
+    // Consumed during initialize, to open the store for state.
+    open_store: Option<OpenStore<S>>,
 
-use super::proto::gsharedmemory::shared_memory_client::*;
+    // These get initialized during initialize.
+    state: Option<State<S>>,
+    engine_client: Option<MessengerClient<Channel>>,
+    appsender_client: Option<AppSenderClient<Channel>>,
+    // cancelled when the VM shuts down
+    shutdown: CancellationToken,
 
-use super::proto::gkeystore::keystore_client::*;
+    // These are used throughout the function
+    processing_blocks: ProcessingBlocks,
+    preferred_block_id: Option<Id>,
 
-use super::proto::galiasreader::alias_reader_client::*;
+    // block data ready to propose
+    mempool: Mempool,
+    gossiper: Gossiper,
 
-const STATIC_HANDLERS_SERVICE_ID: ServiceId = 1;
-const VM_API_HANDLERS_SERVICE_ID: ServiceId = 2;
+    // requests to and from peers
+    network: AppNetwork,
+    peers: Peers,
 
-// Copied from: https://github.com/ava-labs/avalanchego/blob/master/snow/engine/common/http_handler.go#L11
-// To get a u32 representation of this, just pick any one variant 'as u32'. For example:
-//     lock: Lock::WriteLock as u32
-pub enum Lock {
-    #[allow(dead_code)]
-    Write = 0,
-    #[allow(dead_code)]
-    Read,
+    metrics: Metrics,
+}
 
-    None,
+impl TimestampVm {
+    pub fn new() -> Result<TimestampVm, LandslideError> {
+        TimestampVm::with_open_store(Box::new(RpcDb::new))
+    }
 }
 
-// TimestampVM cannot mutably reference self on all its trait methods.
-// Instead it stores an instance of TimestampVmInterior, which is mutable, and can be
-// modified by the calls to TimestampVm's VM trait.
-pub struct TimestampVmInterior {
-    ctx: Option<Context>,
-    version: Version,
-    grpc_broker: Arc<Mutex<GRpcBroker>>,
+impl<S: KeyValueStore> TimestampVm<S> {
+    // Keeps the chain in the given store, instead of the database avalanchego provides.
+    pub fn with_store(store: S) -> Result<TimestampVm<S>, LandslideError> {
+        TimestampVm::with_open_store(Box::new(move |_| store))
+    }
 
-    // These get initialized during the Initialize RPC call.
-    state: Option<State>,
-    versioned_db_clients: Option<BTreeMap<Version, DatabaseClient<Channel>>>,
-    engine_client: Option<MessengerClient<Channel>>,
-    keystore_client: Option<KeystoreClient<Channel>>,
-    shared_memory_client: Option<SharedMemoryClient<Channel>>,
-    bc_lookup_client: Option<AliasReaderClient<Channel>>,
-    sn_lookup_client: Option<SubnetLookupClient<Channel>>,
-    appsender_client: Option<AppSenderClient<Channel>>,
+    fn with_open_store(open_store: OpenStore<S>) -> Result<TimestampVm<S>, LandslideError> {
+        let mut network = AppNetwork::new();
+        network.register::<HasBlockRequest>()?;
 
-    // These are used throughout the function
-    verified_blocks: HashMap<Id, Block>,
-    preferred_block_id: Option<Id>,
+        // replaced by the chain's config during initialize
+        let config = Config::default();
 
-    // blocks ready to propose
-    mem_pool: Vec<[u8; BLOCK_DATA_LEN]>,
-}
+        Ok(TimestampVm {
+            ctx: None,
+            version: Version::new(0, 1, 0),
+            lifecycle: Lifecycle::Initializing,
+            mempool: Mempool::new(config.mempool_max_size),
+            gossiper: Gossiper::new(config.gossip_cache_size, config.gossip_max_per_second),
+            config,
+            upgrades: Upgrades::default(),
+            open_store: Some(open_store),
+
+            state: None,
+            engine_client: None,
+            appsender_client: None,
+            shutdown: CancellationToken::new(),
+
+            processing_blocks: ProcessingBlocks::new(),
+            preferred_block_id: None,
+            network,
+            peers: Peers::new(),
+
+            metrics: Metrics::new()?,
+        })
+    }
+
+    fn apply_config(&mut self, config: Config) {
+        log::info!("Using config: {:?}", config);
+        // validated when parsed
+        if let Ok(Some(level)) = config.log_level_filter() {
+            log::set_max_level(level);
+        }
 
-impl TimestampVmInterior {
-    async fn mut_state_status(&mut self) -> Result<&mut State, Status> {
-        self.mut_state().await.map_err(into_status)
+        self.mempool = Mempool::new(config.mempool_max_size);
+        self.gossiper = Gossiper::new(config.gossip_cache_size, config.gossip_max_per_second);
+        self.config = config;
+    }
+
+    // Whether a fork's rules apply to the block
+    fn is_fork_active(&self, fork: Fork, block: &Block) -> bool {
+        self.upgrades.is_active(
+            fork,
+            block.height(),
+            block.timestamp().offsetdatetime().unix_timestamp(),
+        )
     }
 
-    async fn mut_state(&mut self) -> Result<&mut State, LandslideError> {
+    async fn last_accepted_block_age_secs(&mut self) -> Result<i64, LandslideError> {
+        let last_accepted_id = self.last_accepted().await?;
+        let block = self
+            .mut_state()
+            .await?
+            .get_block(&last_accepted_id)
+            .await?
+            .ok_or_else(|| {
+                LandslideError::Other(anyhow!(
+                    "Last accepted block {} was not found",
+                    last_accepted_id
+                ))
+            })?;
+
+        Ok((OffsetDateTime::now_utc() - *block.timestamp().offsetdatetime()).whole_seconds())
+    }
+
+    fn transition(&mut self, to: Lifecycle) -> Result<(), LandslideError> {
+        self.lifecycle = self.lifecycle.transition(to)?;
+        log::info!("TimestampVm is now {:?}", self.lifecycle);
+        Ok(())
+    }
+
+    async fn mut_state(&mut self) -> Result<&mut State<S>, LandslideError> {
         self.state
             .as_mut()
             .ok_or(LandslideError::StateNotInitialized)
@@ -146,96 +219,231 @@ impl TimestampVmInterior {
             "Genesis storage block created with Id: {}",
             genesis_block_id
         );
-        state.put_block(genesis_block.clone()).await?;
-        log::info!(
-            "Genesis storage block with Id {} put in database successfully.",
-            genesis_block_id
-        );
-        self.accept_block(genesis_block).await?;
+        // The genesis block, its acceptance and the initialized flag are written
+        // together, so a crash can never leave a half-initialized state behind.
+        let mut batch = Batch::new();
+        Self::stage_accept(&mut batch, genesis_block)?;
+        batch.set_state_initialized();
+        state.write_batch(batch).await?;
         log::info!(
             "Genesis storage block with Id {} was accepted by this node.",
             genesis_block_id
         );
-
-        // reacquire state since we need to release writable_interior to pass into accept_block
-        let state = self.mut_state_status().await?;
-        state.set_state_initialized().await?;
         log::info!("State set to initialized, so it won't hapen again.");
 
         Ok(())
     }
 
-    async fn set_preference(&mut self, preferred_block_id: Id) {
-        log::trace!("setting preferred block id...");
-        self.preferred_block_id = Some(preferred_block_id)
-    }
+    // Databases written before the height index existed have no index entries.
+    // Walk back from the last accepted block, indexing accepted blocks until
+    // one is found that is already indexed (or the genesis block is reached).
+    // The index is written HEIGHT_INDEX_CHUNK_SIZE heights at a time, along with where to
+    // carry on from, so an interrupted backfill resumes rather than starting over.
+    async fn index_heights(&mut self) -> Result<(), LandslideError> {
+        let state = self.mut_state().await?;
 
-    async fn open_connection(
-        &mut self,
-        service_id: ServiceId,
-        target: &str,
-    ) -> Result<Channel, Status> {
-        log::trace!(
-            "opening a new connection to host for service_id: {}",
-            service_id
-        );
-        Ok(self
-            .grpc_broker
-            .lock()
-            .await
-            .dial_to_host_service(service_id)
-            .await
-            .with_context(|| {
-                format!(
-                    "Failed to dial a connection to the {} server {}",
-                    target, service_id,
-                )
-            })
-            .map_err(|e| e.into())
-            .map_err(into_status)?)
-    }
+        let mut block_id = match state.get_height_index_cursor().await? {
+            Some(block_id) => {
+                log::info!("Resuming the height index backfill from block {}", block_id);
+                block_id
+            }
+            None => match state.get_last_accepted_block_id().await? {
+                Some(block_id) => block_id,
+                None => return Ok(()),
+            },
+        };
 
-    pub async fn new_grpc_server<S>(
-        &mut self,
-        service_id: ServiceId,
-        server: S,
-    ) -> Result<ServiceId, Status>
-    where
-        S: Service<HyperRequest<Body>, Response = HyperResponse<BoxBody>>
-            + NamedService
-            + Clone
-            + Send
-            + 'static,
-        <S as Service<HyperRequest<Body>>>::Future: Send + 'static,
-        <S as Service<HyperRequest<Body>>>::Error: Into<Box<dyn StdError + Send + Sync>> + Send,
-    {
-        log::trace!("Opening a new gRPC server through the grpc broker...");
-        self.grpc_broker
-            .lock()
-            .await
-            .new_grpc_server_with_service_id(service_id, server)
-            .await
-            .context("Unable to create a new GHttp Server server for handlers")
-            .map_err(|e| e.into())
-            .map_err(into_status)
-    }
+        let mut batch = Batch::new();
+        let mut indexed_count: u64 = 0;
+        while let Some(block) = state.get_block(&block_id).await? {
+            if state.get_block_id_at_height(block.height()).await?.as_ref() == Some(&block_id) {
+                break;
+            }
+
+            batch.set_block_id_at_height(block.height(), &block_id);
+            indexed_count += 1;
+
+            if block.height() == 0 {
+                break;
+            }
+            block_id = block.parent_id().clone();
+
+            if indexed_count.is_multiple_of(HEIGHT_INDEX_CHUNK_SIZE) {
+                batch.set_height_index_cursor(&block_id);
+                state.write_batch(std::mem::take(&mut batch)).await?;
+            }
+        }
 
-    async fn version(&self) -> Result<Response<VersionResponse>, Status> {
-        let version = self.version.to_string();
-        log::info!("responding with version {}", version);
-        Ok(Response::new(VersionResponse {
-            version: self.version.to_string(),
-        }))
+        batch.delete_height_index_cursor();
+        state.write_batch(batch).await?;
+        if indexed_count > 0 {
+            log::info!(
+                "Indexed {} previously accepted blocks by height",
+                indexed_count
+            );
+        }
+
+        Ok(())
     }
 
     async fn propose_block(&mut self, data: &[u8]) -> Result<(), LandslideError> {
         log::trace!("Proposing a new block...");
-        let fixed_array: [u8; BLOCK_DATA_LEN] = data.try_into()?;
-        self.mem_pool.push(fixed_array);
+        self.lifecycle.ensure_normal_op()?;
+        // proposals come from users, so data of the wrong length is theirs to fix
+        let fixed_array: [u8; BLOCK_DATA_LEN] = match data.try_into() {
+            Ok(fixed_array) => fixed_array,
+            Err(err) => {
+                return Err(LandslideError::InvalidArgument {
+                    argument: "data",
+                    source: Box::new(LandslideError::TryFromSlice(err)),
+                })
+            }
+        };
+        self.add_to_mempool(fixed_array).await?;
+
+        self.gossiper.observe(&fixed_array);
+        self.gossip(&fixed_array).await;
 
         self.notify_block_ready().await
     }
 
+    // Adds data to the mempool, and persists it there
+    async fn add_to_mempool(&mut self, data: [u8; BLOCK_DATA_LEN]) -> Result<(), LandslideError> {
+        let seq = self.mempool.add(data)?;
+
+        let mut batch = Batch::new();
+        batch.put_mempool_entry(seq, &data);
+        if let Err(err) = self.mut_state().await?.write_batch(batch).await {
+            self.mempool.remove(&data);
+            return Err(err);
+        }
+        self.metrics.mempool_size.set(self.mempool.len() as i64);
+
+        Ok(())
+    }
+
+    // Sends data to peers, unless gossip is being rate limited.
+    // The data is in the local mempool either way, so failing to gossip is only logged.
+    async fn gossip(&mut self, data: &[u8; BLOCK_DATA_LEN]) {
+        if !self.config.gossip_enabled || self.lifecycle != Lifecycle::NormalOp {
+            return;
+        }
+        if !self.gossiper.allow() {
+            log::debug!("Gossip is rate limited, not gossiping data");
+            return;
+        }
+
+        let msg = match gossip::encode(data) {
+            Ok(msg) => msg,
+            Err(err) => {
+                log::warn!("Unable to encode gossip message: {}", err);
+                return;
+            }
+        };
+
+        // Until any peers connect, avalanchego picks who to gossip to
+        let targets = self.peers.targets(GOSSIP_PEERS);
+        match self.appsender_client.as_mut() {
+            Some(appsender_client) => {
+                let result = if targets.is_empty() {
+                    appsender_client
+                        .send_app_gossip(SendAppGossipMsg { msg })
+                        .await
+                } else {
+                    appsender_client
+                        .send_app_gossip_specific(SendAppGossipSpecificMsg {
+                            node_i_ds: targets,
+                            msg,
+                        })
+                        .await
+                };
+                if let Err(err) = result {
+                    log::warn!("Unable to gossip data to peers: {}", err);
+                }
+            }
+            None => log::debug!("dropped gossip to peers..."),
+        }
+    }
+
+    // Brings back the proposals that were waiting to be built before a restart
+    async fn load_mempool(&mut self) -> Result<(), LandslideError> {
+        let entries = self.mut_state().await?.get_mempool_entries().await?;
+        if !entries.is_empty() {
+            log::info!("Restoring {} proposals into the mempool", entries.len());
+        }
+
+        for (seq, data) in entries {
+            self.mempool.restore(seq, data);
+        }
+        self.metrics.mempool_size.set(self.mempool.len() as i64);
+
+        Ok(())
+    }
+
+    // Whether a block on top of this one could still be accepted: only blocks on the
+    // last accepted block, or on one that may still be accepted, can be.
+    async fn can_build_on(&mut self, block_id: &Id) -> Result<bool, LandslideError> {
+        Ok(self.processing_blocks.contains(block_id) || &self.last_accepted().await? == block_id)
+    }
+
+    // A block's status as this VM knows it: processing blocks are kept in memory and
+    // decided blocks in state. Anything else was never verified.
+    async fn current_status(&mut self, block_id: &Id) -> Result<BlockStatus, LandslideError> {
+        if self.processing_blocks.contains(block_id) {
+            return Ok(BlockStatus::Processing);
+        }
+
+        Ok(match self.mut_state().await?.get_block(block_id).await? {
+            Some(block) if block.status.decided() => block.status,
+            _ => BlockStatus::Unknown,
+        })
+    }
+
+    // Asks a peer whether it has a block, or the next connected peer if none is given.
+    // Returns who was asked, and their answer. The request is sent under the VM's lock,
+    // but the response is awaited without it, since it is delivered through the VM.
+    async fn ask_has_block(
+        vm: Arc<RwLock<Self>>,
+        node_id: Option<Vec<u8>>,
+        block_id: Id,
+    ) -> Result<(Vec<u8>, bool), LandslideError> {
+        let (node_id, pending, shutdown) = {
+            let mut vm = vm.write().await;
+            let vm = &mut *vm;
+            let node_id = match node_id {
+                Some(node_id) => node_id,
+                None => vm.peers.target().ok_or(LandslideError::NoPeersConnected)?,
+            };
+            let appsender_client = vm
+                .appsender_client
+                .as_mut()
+                .ok_or(LandslideError::StateNotInitialized)?;
+            let pending = vm
+                .network
+                .send_request(appsender_client, &node_id, &HasBlockRequest { block_id })
+                .await?;
+            (node_id, pending, vm.shutdown.clone())
+        };
+
+        let request_id = pending.request_id;
+        tokio::select! {
+            response = tokio::time::timeout(REQUEST_TIMEOUT, pending.recv()) => match response {
+                Ok(response) => Ok((node_id, response?.has_block)),
+                Err(_) => {
+                    // avalanchego should have failed it by now, but nobody waits for it any more
+                    vm.write().await.network.on_request_failed(&node_id, request_id);
+                    Err(LandslideError::AppRequestFailed {
+                        node_id: hex::encode(&node_id),
+                        request_id,
+                    })
+                }
+            },
+            _ = shutdown.cancelled() => Err(LandslideError::NotInNormalOp {
+                lifecycle: Lifecycle::ShuttingDown,
+            }),
+        }
+    }
+
     async fn notify_block_ready(&mut self) -> Result<(), LandslideError> {
         log::trace!("Notifying engine that a new block is ready...");
         match self.engine_client.as_mut() {
@@ -252,25 +460,472 @@ impl TimestampVmInterior {
         Ok(())
     }
 
-    async fn accept_block(&mut self, mut block: Block) -> Result<(), LandslideError> {
-        let state = self.mut_state().await?;
+    // Builds (and verifies) a block with the given data, on top of the preferred block
+    async fn build_on_preferred(
+        &mut self,
+        block_data: [u8; BLOCK_DATA_LEN],
+    ) -> Result<Block, LandslideError> {
+        // The preference stays until consensus changes it, so more blocks can be built on it.
+        // Until consensus states one, the last accepted block is as good as preferred.
+        let preferred_block_id = match self.preferred_block_id.clone() {
+            Some(preferred_block_id) => preferred_block_id,
+            None => self.last_accepted().await?,
+        };
+
+        // Gets Preferred Block, which may be the last accepted block or still processing
+        let preferred_block = self.get_block(&preferred_block_id).await?.ok_or_else(|| {
+            LandslideError::BlockNotFound {
+                block_id: preferred_block_id.clone(),
+            }
+        })?;
+        if !self.can_build_on(&preferred_block_id).await? {
+            return Err(LandslideError::PreferredNotBuildable {
+                block_id: preferred_block_id,
+                status: preferred_block.status,
+            });
+        }
+        let preferred_height = preferred_block.height();
+
+        // A new block is always after its parent, whatever this node's clock says. Block
+        // timestamps are whole seconds, so a block built within its parent's second is a second later.
+        let preferred_timestamp = *preferred_block.timestamp().offsetdatetime();
+        let timestamp = OffsetDateTime::now_utc().max(preferred_timestamp + Duration::SECOND);
 
+        // Build the block with preferred height
+        let block = Block::new(
+            preferred_block_id,
+            preferred_height + 1,
+            block_data,
+            timestamp,
+            BlockStatus::Processing,
+        )?;
+        self.verify_block(block.clone()).await?;
+        Ok(block)
+    }
+
+    // Stages every write that accepting a block makes: the block itself with its
+    // Accepted status, its height index entry, and the last accepted pointer.
+    fn stage_accept(batch: &mut Batch, mut block: Block) -> Result<Id, LandslideError> {
         block.status = BlockStatus::Accepted;
         let bid = block.generate_id()?.clone();
         log::info!("Accepting block with id: {}", bid);
 
-        state.put_block(block).await?;
-        log::info!("Put accepted block into database with id: {}", bid);
+        let height = block.height();
+        batch.put_block(block)?;
+        batch.set_block_id_at_height(height, &bid);
+        batch.set_last_accepted_block_id(&bid);
+
+        Ok(bid)
+    }
+}
+
+#[tonic::async_trait]
+impl<S: KeyValueStore> ChainVm for TimestampVm<S> {
+    type Block = Block;
+
+    async fn initialize(&mut self, host: Host) -> Result<(), LandslideError> {
+        log::info!("Initializing TimestampVm version {}", self.version);
+        self.apply_config(Config::parse(&host.config_bytes)?);
+        self.upgrades = Upgrades::parse(&host.upgrade_bytes)?;
+        log::info!("Using upgrades: {:?}", self.upgrades);
+        self.ctx = Some(host.ctx);
+        self.engine_client = Some(host.engine);
+        self.appsender_client = Some(host.app_sender);
+        self.shutdown = host.shutdown;
+
+        let open_store = self.open_store.take().ok_or_else(|| {
+            LandslideError::Other(anyhow!("The store for this VM's state was already opened by an earlier call to initialize."))
+        })?;
+        self.state = Some(State::new(open_store(host.db), &self.metrics));
+        log::info!("Initialized state for this VM");
+
+        self.init_genesis(host.genesis_bytes.as_ref())
+            .await
+            .context("Failed to initialize genesis block.")?;
+        log::trace!("TimestampVm::Initialize genesis initialized");
+
+        self.index_heights()
+            .await
+            .context("Failed to index accepted blocks by height.")?;
+
+        self.load_mempool()
+            .await
+            .context("Failed to restore the mempool.")?;
+
+        let labid = self.last_accepted().await?;
+        log::trace!(
+            "TimestampVm::Initialize obtained last accepted block id: {}",
+            labid
+        );
+        self.set_preference(labid).await
+    }
+
+    async fn bootstrapping(&mut self) -> Result<(), LandslideError> {
+        self.transition(Lifecycle::Bootstrapping)
+    }
+
+    // Proposals that arrived before (or were restored from before a restart)
+    // can be built now
+    async fn bootstrapped(&mut self) -> Result<(), LandslideError> {
+        self.transition(Lifecycle::NormalOp)?;
+        if !self.mempool.is_empty() {
+            self.notify_block_ready().await?;
+        }
+
+        Ok(())
+    }
+
+    // Once shut down, the VM has no state or clients left, so later calls fail
+    // with StateNotInitialized (or the like) rather than reaching a closed database.
+    async fn shutdown(&mut self) -> Result<(), LandslideError> {
+        if self.lifecycle == Lifecycle::ShuttingDown {
+            return Ok(());
+        }
+        self.transition(Lifecycle::ShuttingDown)?;
+        self.shutdown.cancel();
+
+        // Nobody waits on peers that won't be heard from any more
+        self.network.close();
+        self.engine_client = None;
+        self.appsender_client = None;
+
+        // Proposals were persisted as they came in. Closing the store flushes them.
+        if let Some(mut state) = self.state.take() {
+            state.close().await?;
+        }
+
+        Ok(())
+    }
 
-        state.set_last_accepted_block_id(&bid).await?;
-        log::info!("Setting last accepted block id in database to: {}", bid);
+    async fn create_handlers(vm: Arc<RwLock<Self>>) -> Result<Handlers, LandslideError> {
+        let (metrics, api_enabled) = {
+            let vm = vm.read().await;
+            (vm.metrics.clone(), vm.config.api_enabled)
+        };
+        if !api_enabled {
+            log::info!("The chain's API is disabled by config");
+            return Ok(Handlers::new());
+        }
+
+        Ok(Handlers::from([(
+            "".to_string(),
+            handlers::new(vm, metrics),
+        )]))
+    }
+
+    async fn create_static_handlers(&self) -> Result<Handlers, LandslideError> {
+        Ok(Handlers::from([(
+            "".to_string(),
+            static_handlers::new(self.metrics.clone()),
+        )]))
+    }
+
+    async fn health_check(&mut self) -> Result<String, LandslideError> {
+        // Checking whether state is initialized doubles as a round-trip to the database
+        let started = Instant::now();
+        let state_initialized = match self.state.as_mut() {
+            Some(state) => state.is_state_initialized().await,
+            None => Err(LandslideError::StateNotInitialized),
+        };
+        let database = DatabaseHealth {
+            reachable: state_initialized.is_ok(),
+            latency_ms: started.elapsed().as_millis(),
+            error: state_initialized.as_ref().err().map(|err| err.to_string()),
+        };
+        let state_initialized = matches!(state_initialized, Ok(true));
+
+        let last_accepted_block_age_secs = if state_initialized {
+            match self.last_accepted_block_age_secs().await {
+                Ok(age) => Some(age),
+                Err(err) => {
+                    log::warn!("Unable to find the age of the last accepted block: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut report = HealthReport {
+            healthy: false,
+            lifecycle: self.lifecycle,
+            state_initialized,
+            database,
+            last_accepted_block_age_secs,
+            mempool: MempoolHealth {
+                size: self.mempool.len(),
+                max_size: self.config.mempool_max_size,
+            },
+            connected_peers: self.peers.len(),
+        };
+        let healthy = report.check();
+
+        let details = serde_json::to_string(&report)?;
+        if !healthy {
+            return Err(LandslideError::Unhealthy { details });
+        }
+        Ok(details)
+    }
+
+    fn metrics_registry(&self) -> Option<&Registry> {
+        Some(self.metrics.registry())
+    }
+
+    // Data gossiped by a peer goes into the mempool, and on to other peers,
+    // the first time it is seen. Bad or unwanted gossip is dropped, not returned as an error.
+    async fn app_gossip(&mut self, node_id: &[u8], msg: &[u8]) -> Result<(), LandslideError> {
+        self.peers.observe_message(node_id);
+        if self.lifecycle != Lifecycle::NormalOp {
+            log::trace!("Dropping gossip while {:?}", self.lifecycle);
+            return Ok(());
+        }
+
+        let data = match gossip::decode(msg) {
+            Ok(data) => data,
+            Err(err) => {
+                log::debug!(
+                    "Dropping malformed gossip from node {}: {}",
+                    hex::encode(node_id),
+                    err
+                );
+                return Ok(());
+            }
+        };
+
+        if !self.gossiper.observe(&data) {
+            log::trace!("Dropping gossip that was already seen");
+            return Ok(());
+        }
+
+        match self.add_to_mempool(data).await {
+            Ok(()) => {}
+            Err(err @ LandslideError::MempoolDuplicate { .. })
+            | Err(err @ LandslideError::MempoolFull { .. }) => {
+                log::debug!(
+                    "Dropping gossip from node {}: {}",
+                    hex::encode(node_id),
+                    err
+                );
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+
+        self.gossip(&data).await;
+        self.notify_block_ready().await
+    }
+
+    // avalanchego also reports the node itself as connected, which isn't a peer
+    async fn connected(&mut self, node_id: &[u8], version: &str) -> Result<(), LandslideError> {
+        if self.ctx.as_ref().map(|ctx| &ctx.node_id[..]) == Some(node_id) {
+            return Ok(());
+        }
+
+        log::debug!(
+            "Node {} connected, with version {}",
+            hex::encode(node_id),
+            version
+        );
+        self.peers.connected(node_id, version);
+        self.metrics.connected_peers.set(self.peers.len() as i64);
+        Ok(())
+    }
+
+    async fn disconnected(&mut self, node_id: &[u8]) -> Result<(), LandslideError> {
+        if self.peers.disconnected(node_id) {
+            log::debug!("Node {} disconnected", hex::encode(node_id));
+        }
+        self.metrics.connected_peers.set(self.peers.len() as i64);
+        Ok(())
+    }
+
+    // Requests from peers are answered as they come in. Requests that can't be
+    // answered (expired, unknown or malformed) are dropped, and fail on the peer's side.
+    async fn app_request(
+        &mut self,
+        node_id: &[u8],
+        request_id: u32,
+        deadline: OffsetDateTime,
+        request: &[u8],
+    ) -> Result<(), LandslideError> {
+        self.peers.observe_message(node_id);
+        let request = match self
+            .network
+            .receive_request(node_id, request_id, deadline, request)
+        {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                log::debug!(
+                    "Dropping malformed request {} from node {}: {}",
+                    request_id,
+                    hex::encode(node_id),
+                    err
+                );
+                return Ok(());
+            }
+        };
+
+        if request.is::<HasBlockRequest>() {
+            let has_block = match request.decode::<HasBlockRequest>() {
+                Ok(HasBlockRequest { block_id }) => self.get_block(&block_id).await?.is_some(),
+                Err(err) => {
+                    log::debug!("Dropping malformed request {}: {}", request_id, err);
+                    return Ok(());
+                }
+            };
 
-        self.verified_blocks.remove(&bid);
+            let response = HasBlockResponse { has_block };
+            match self.appsender_client.as_mut() {
+                Some(appsender_client) => {
+                    self.network
+                        .send_response::<HasBlockRequest>(appsender_client, &request, &response)
+                        .await?
+                }
+                None => log::debug!("dropped response to request {}...", request_id),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn app_request_failed(
+        &mut self,
+        node_id: &[u8],
+        request_id: u32,
+    ) -> Result<(), LandslideError> {
+        self.network.on_request_failed(node_id, request_id);
+        Ok(())
+    }
+
+    async fn app_response(
+        &mut self,
+        node_id: &[u8],
+        request_id: u32,
+        response: &[u8],
+    ) -> Result<(), LandslideError> {
+        self.peers.observe_message(node_id);
+        self.network
+            .on_response(node_id, request_id, Vec::from(response));
+        Ok(())
+    }
+
+    fn version(&self) -> Version {
+        self.version.clone()
+    }
+
+    async fn last_accepted(&mut self) -> Result<Id, LandslideError> {
+        self.mut_state().await?.get_last_accepted_block_id().await?.ok_or_else(|| LandslideError::Other(anyhow!("Unable to find last accepted block id in the database. This is unusual since initialize should have initialized the genesis block at least.")))
+    }
+
+    async fn set_preference(&mut self, preferred_block_id: Id) -> Result<(), LandslideError> {
+        log::trace!("setting preferred block id...");
+        self.preferred_block_id = Some(preferred_block_id);
+        Ok(())
+    }
+
+    async fn build_block(&mut self) -> Result<Block, LandslideError> {
+        let _timer = self
+            .metrics
+            .block_operation_duration
+            .with_label_values(&["build"])
+            .start_timer();
+        self.lifecycle.ensure_normal_op()?;
+
+        // Get the value to put in the new block
+        let block_data = self
+            .mempool
+            .pop()
+            .ok_or_else(|| LandslideError::NothingToBuild)?;
+        self.metrics.mempool_size.set(self.mempool.len() as i64);
+
+        let block = match self.build_on_preferred(block_data).await {
+            Ok(block) => block,
+            Err(err) => {
+                // Nothing was built, so the data goes back to the front of the line
+                self.mempool.requeue(block_data);
+                self.metrics.mempool_size.set(self.mempool.len() as i64);
+                return Err(err);
+            }
+        };
+
+        // Notify consensus engine that there are more pending data for blocks
+        // (if that is the case) when done building this block
+        if !self.mempool.is_empty() {
+            self.notify_block_ready().await?;
+        }
+
+        self.metrics.blocks_built.inc();
+        Ok(block)
+    }
+
+    // Looks up a block by its Id, first among the blocks verified in memory
+    // (but not yet decided), and then in the database.
+    async fn get_block(&mut self, block_id: &Id) -> Result<Option<Block>, LandslideError> {
+        if let Some(block) = self.processing_blocks.get(block_id) {
+            log::trace!("found block {} among processing blocks", block_id);
+            return Ok(Some(block.clone()));
+        }
+
+        self.mut_state().await?.get_block(block_id).await
+    }
+
+    // Parses a block from its bytes. If this block is already known, the known
+    // block (with its current status) is returned instead.
+    async fn parse_block(&mut self, bytes: &[u8]) -> Result<Block, LandslideError> {
+        let mut block = Block::from_bytes(bytes)?;
+        block.status = BlockStatus::Processing;
+
+        let block_id = block.generate_id()?.clone();
+        match self.get_block(&block_id).await? {
+            Some(existing_block) => Ok(existing_block),
+            None => Ok(block),
+        }
+    }
+
+    async fn accept_block(&mut self, mut block: Block) -> Result<(), LandslideError> {
+        let _timer = self
+            .metrics
+            .block_operation_duration
+            .with_label_values(&["accept"])
+            .start_timer();
+        let block_id = block.generate_id()?.clone();
+        if !self
+            .current_status(&block_id)
+            .await?
+            .needs_decision(&block_id, BlockStatus::Accepted)?
+        {
+            return Ok(());
+        }
+
+        let state = self.mut_state().await?;
+        let data = Vec::from(block.data());
+        let parent_id = block.parent_id().clone();
+
+        // The block's data is done with, whichever node proposed or built it
+        let mut batch = Batch::new();
+        let bid = Self::stage_accept(&mut batch, block)?;
+        batch.delete_mempool_entry(&data);
+        state.write_batch(batch).await?;
+        log::info!("Wrote accepted block {} to the database", bid);
+
+        if self.mempool.remove(&data) {
+            log::debug!("Removed the data in block {} from the mempool", bid);
+            self.metrics.mempool_size.set(self.mempool.len() as i64);
+        }
+        // so late gossip doesn't bring it back into the mempool
+        self.gossiper.observe(&data);
+
+        // Whatever competed with this block can never be accepted now
+        for conflicting in self.processing_blocks.accept(&bid, &parent_id) {
+            self.reject_block(conflicting).await?;
+        }
         log::info!(
-            "Removing from verified blocks, since it is now accepted, the block id: {}",
+            "Removed block {} and the branches competing with it from processing blocks",
             bid
         );
 
+        self.metrics.blocks_accepted.inc();
         Ok(())
     }
 
@@ -279,15 +934,35 @@ impl TimestampVmInterior {
     // b.parent.Timestamp < b.Timestamp <= [local time] + 1 hour
     async fn verify_block(&mut self, mut block: Block) -> Result<(), LandslideError> {
         log::trace!("Verifying block...");
-        let state = self.mut_state().await?;
-
+        let _timer = self
+            .metrics
+            .block_operation_duration
+            .with_label_values(&["verify"])
+            .start_timer();
         let bid = block.generate_id()?.clone();
         let parent_id = block.parent_id().clone();
 
-        let parent_block = state.get_block(&parent_id).await?.ok_or_else(||
-            LandslideError::Other(anyhow!("TimestampVm::verify_block - Parent Block ID {} was not found in the database for Block being verified with Id {}", parent_id, bid)))?;
+        // The parent may be still processing itself, so chains of unaccepted blocks can form
+        let parent_block =
+            self.get_block(&parent_id)
+                .await?
+                .ok_or_else(|| LandslideError::NoParentBlock {
+                    block_id: bid.clone(),
+                    parent_block_id: parent_id.clone(),
+                })?;
         log::info!("retrieved parent block");
 
+        // Only blocks on a parent that was accepted, or still may be, can be accepted
+        if !self.can_build_on(&parent_id).await? {
+            return Err(LandslideError::InvalidBlock {
+                block_id: bid,
+                reason: format!(
+                    "its parent {} is {:?}, and not the last accepted block, so it can never be accepted",
+                    parent_id, parent_block.status
+                ),
+            });
+        }
+
         // Ensure [b]'s height comes right after its parent's height
         if parent_block.height() + 1 != block.height() {
             let err = LandslideError::ParentBlockHeightUnexpected {
@@ -302,531 +977,750 @@ impl TimestampVmInterior {
         let pbts = *parent_block.timestamp().offsetdatetime();
         // Ensure [b]'s timestamp is after its parent's timestamp.
         if bts < pbts {
-            return Err(LandslideError::Other(anyhow!("The current block {}'s  timestamp {}, is before the parent block {}'s timestamp {}, which is invalid for a Blockchain.", bid, bts, parent_id, pbts)));
-        }
-
-        // Ensure [b]'s timestamp is not more than an hour
-        // ahead of this node's time
-        let now = OffsetDateTime::now_utc();
-        let one_hour_from_now = match now.checked_add(Duration::hours(1)) {
-            Some(t) => t,
-            None => {
-                return Err(LandslideError::Other(anyhow!(
-                    "Unable to compute time 1 hour from now."
-                )))
-            }
-        };
+            return Err(LandslideError::InvalidBlock {
+                block_id: bid,
+                reason: format!(
+                    "its timestamp {} is before its parent {}'s timestamp {}",
+                    bts, parent_id, pbts
+                ),
+            });
+        }
+        // ...and, since the strict-timestamps fork, strictly after it
+        if bts == pbts && self.is_fork_active(Fork::StrictTimestamps, &block) {
+            return Err(LandslideError::InvalidBlock {
+                block_id: bid,
+                reason: format!(
+                    "its timestamp {} is the same as its parent {}'s, which is invalid since the strict-timestamps fork",
+                    bts, parent_id
+                ),
+            });
+        }
+
+        // Ensure [b]'s timestamp is not more than max-future-block-time-secs
+        // ahead of this node's time. Blocks the network accepted in the past are
+        // bootstrapped regardless, since this node's clock may not be trusted yet.
+        if self.lifecycle != Lifecycle::Bootstrapping {
+            let now = OffsetDateTime::now_utc();
+            let max_future_block_time = self.config.max_future_block_time();
+            let latest_allowed = match now.checked_add(max_future_block_time) {
+                Some(t) => t,
+                None => {
+                    return Err(LandslideError::Other(anyhow!(
+                        "Unable to compute time {} from now.",
+                        max_future_block_time
+                    )))
+                }
+            };
 
-        if bts >= one_hour_from_now {
-            return Err(LandslideError::Other(anyhow!("The current block {}'s  timestamp {}, is more than 1 hour in the future compared to this node's time {}", bid, bts, now)));
+            if bts >= latest_allowed {
+                return Err(LandslideError::InvalidBlock {
+                    block_id: bid,
+                    reason: format!(
+                        "its timestamp {} is more than {} in the future compared to this node's time {}",
+                        bts, max_future_block_time, now
+                    ),
+                });
+            }
         }
 
         log::info!("Adding block to list of verified blocks: {:?}", bid);
         // Put that block to verified blocks in memory
-        self.verified_blocks.insert(bid, block);
+        self.processing_blocks.insert(bid, block);
 
+        self.metrics.blocks_verified.inc();
         Ok(())
     }
 
     // Reject sets this block's status to Rejected and saves the status in state
     // Recall that b.vm.DB.Commit() must be called to persist to the DB
-    #[allow(dead_code)]
     async fn reject_block(&mut self, mut block: Block) -> Result<(), LandslideError> {
-        let state = self.mut_state().await?;
+        let _timer = self
+            .metrics
+            .block_operation_duration
+            .with_label_values(&["reject"])
+            .start_timer();
+        let block_id = block.generate_id()?.clone();
+        if !self
+            .current_status(&block_id)
+            .await?
+            .needs_decision(&block_id, BlockStatus::Rejected)?
+        {
+            return Ok(());
+        }
 
+        let state = self.mut_state().await?;
         block.status = BlockStatus::Rejected;
 
-        let block_id = block.generate_id()?.clone();
-
+        let data: [u8; BLOCK_DATA_LEN] = block.data().try_into()?;
         state.put_block(block).await?;
 
-        self.verified_blocks.remove(&block_id);
+        self.processing_blocks.remove(&block_id);
+
+        // Data built into a rejected block goes back to be built again,
+        // unless it already made it into an accepted block.
+        if self.mempool.requeue(data) {
+            log::debug!("Requeued the data in rejected block {}", block_id);
+            self.metrics.mempool_size.set(self.mempool.len() as i64);
+        }
 
+        self.metrics.blocks_rejected.inc();
         Ok(())
     }
 }
 
-pub struct TimestampVm {
-    interior: Arc<RwLock<TimestampVmInterior>>,
-}
-
-impl TimestampVm {
-    pub fn new(grpc_broker: Arc<Mutex<GRpcBroker>>) -> Result<TimestampVm, LandslideError> {
-        Ok(TimestampVm {
-            interior: Arc::new(RwLock::new(TimestampVmInterior {
-                ctx: None,
-                version: Version::new(0, 1, 0),
-                grpc_broker,
-
-                state: None,
-                versioned_db_clients: None,
-                engine_client: None,
-                keystore_client: None,
-                shared_memory_client: None,
-                bc_lookup_client: None,
-                sn_lookup_client: None,
-                appsender_client: None,
-
-                verified_blocks: HashMap::new(),
-                preferred_block_id: None,
-                mem_pool: Vec::new(),
-            })),
-        })
+#[cfg(test)]
+mod test {
+    use super::*;
+    use landslide::chainvm::get_ancestors;
+    use landslide::kvstore::MemoryStore;
+    use std::time::Duration as StdDuration;
+
+    async fn test_vm() -> TimestampVm<MemoryStore> {
+        let mut vm = TimestampVm::with_store(MemoryStore::new()).unwrap();
+        vm.state = Some(State::new(MemoryStore::new(), &vm.metrics));
+        vm.init_genesis(b"genesis").await.unwrap();
+        vm.bootstrapping().await.unwrap();
+        vm.bootstrapped().await.unwrap();
+        vm
     }
-}
-
-#[tonic::async_trait]
-impl Vm for TimestampVm {
-    async fn initialize(
-        &self,
-        request: Request<InitializeRequest>,
-    ) -> Result<Response<InitializeResponse>, Status> {
-        log::trace!("called");
-        let mut writable_interior = self.interior.write().await;
-
-        log::trace!("Initialize Calling Version...");
-
-        let version =
-            writable_interior.version().await
-            .context("Failed calling version on the mutable interior (but passed immutably) of the TimestampVm")
-            .map_err(|e| e.into())
-            .map_err(into_status)?;
-
-        log::trace!("Initialize obtained VM version: {:?}", version);
-
-        let ir = request.into_inner();
-        log::info!("Full Request: {:?}", ir,);
-
-        writable_interior.ctx = Some(Context {
-            network_id: ir.network_id,
-            subnet_id: ir.subnet_id,
-            chain_id: ir.chain_id,
-            node_id: ir.node_id,
-
-            x_chain_id: ir.x_chain_id,
-            avax_asset_id: ir.avax_asset_id,
-        });
-        log::trace!("setup context from genesis data");
-
-        let mut versioned_db_clients: BTreeMap<Version, DatabaseClient<Channel>> = BTreeMap::new();
-        for db_server in ir.db_servers.iter() {
-            let ver_without_v = db_server.version.trim_start_matches('v');
-            let version = Version::parse(ver_without_v)
-                .with_context(|| format!("In initialize, failed to parse the semver::Version for a VersionedDatabase obtained from the host/client. Version provided by server: {}, with the leading 'v' removed: {}", db_server.version, ver_without_v))
-                .map_err(|e| e.into())
-                .map_err(into_status)?;
-
-            let conn = writable_interior
-                .open_connection(db_server.db_server, "VersionedDatabase")
-                .await?;
-
-            let db_client = DatabaseClient::new(conn);
-            versioned_db_clients.insert(version, db_client);
-            log::info!(
-                "initialized versioned db client for server: {:?}",
-                db_server
-            );
-        }
-        writable_interior.versioned_db_clients = Some(versioned_db_clients);
-        log::trace!("initialized all versioned db clients",);
-
-        let conn = writable_interior
-            .open_connection(ir.engine_server, "engine_server")
-            .await?;
-        writable_interior.engine_client = Some(MessengerClient::new(conn));
-        log::trace!("initialized messenger (engine server) client",);
-
-        let conn = writable_interior
-            .open_connection(ir.keystore_server, "keystore_server")
-            .await?;
-        writable_interior.keystore_client = Some(KeystoreClient::new(conn));
-        log::trace!("initialized keystore client");
-
-        let conn = writable_interior
-            .open_connection(ir.shared_memory_server, "shared_memory_server")
-            .await?;
-        writable_interior.shared_memory_client = Some(SharedMemoryClient::new(conn));
-        log::trace!("initialized shared memory client",);
-
-        let conn = writable_interior
-            .open_connection(ir.bc_lookup_server, "bc_lookup_server")
-            .await?;
-        writable_interior.bc_lookup_client = Some(AliasReaderClient::new(conn));
-        log::trace!("initialized alias reader client");
-
-        let conn = writable_interior
-            .open_connection(ir.sn_lookup_server, "sn_lookup_server")
-            .await?;
-        writable_interior.sn_lookup_client = Some(SubnetLookupClient::new(conn));
-        log::trace!("initialized subnet lookup client",);
-
-        let conn = writable_interior
-            .open_connection(ir.app_sender_server, "app_sender_server")
-            .await?;
-        writable_interior.appsender_client = Some(AppSenderClient::new(conn));
-        log::trace!("initialized app sender client");
-
-        if let Some(versioned_db_clients) = writable_interior.versioned_db_clients.as_ref() {
-            if versioned_db_clients.is_empty() {
-                return Err(Status::unknown("zero versioned_db_clients were found. Unable to proceed without a versioned database."));
-            }
-            if let Some(db_client) = versioned_db_clients.values().rev().next() {
-                log::info!("Initialized state for this VM");
-                let state = State::new(db_client.clone());
-                writable_interior.state = Some(state);
-            } else {
-                return Err(Status::unknown("database client not found, when length was verified to be > 0 a little earlier."));
-            }
-        } else {
-            return Err(Status::unknown("versioned_db_clients was None, when it was just set in this same method a little bit before."));
-        }
 
-        writable_interior
-            .init_genesis(ir.genesis_bytes.as_ref())
-            .await
-            .context("Failed to initialize genesis block.")
-            .map_err(|e| e.into())
-            .map_err(into_status)?;
+    #[tokio::test]
+    async fn test_verify_and_accept_on_memory_store() {
+        let mut vm = test_vm().await;
+        let state = vm.mut_state().await.unwrap();
+        let genesis_id = state.get_last_accepted_block_id().await.unwrap().unwrap();
+        assert!(state.is_state_initialized().await.unwrap());
 
-        log::trace!("TimestampVm::Initialize genesis initialized");
+        let mut block = Block::new(
+            genesis_id.clone(),
+            1,
+            [1; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc(),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        let block_id = block.generate_id().unwrap().clone();
 
-        log::info!("Using state for this VM");
-        let state = writable_interior.mut_state_status().await?;
+        vm.verify_block(block.clone()).await.unwrap();
+        assert!(vm.processing_blocks.contains(&block_id));
 
-        let labid = state
-            .get_last_accepted_block_id()
-            .await
-            .context("Failed to get last accepted block id")
-            .map_err(|e| e.into())
-            .map_err(into_status)?
-            .ok_or_else(||Status::unknown("TimestampVm::initialize - unable to find last accepted block id in the database. This is unusual since the init_genesis() call made within this function a bit earlier, should have initialized the genesis block at least.".to_string()))?;
+        vm.accept_block(block).await.unwrap();
+        assert!(!vm.processing_blocks.contains(&block_id));
 
-        log::trace!(
-            "TimestampVm::Initialize obtained last accepted block id: {}",
-            labid
+        let state = vm.mut_state().await.unwrap();
+        assert_eq!(
+            state.get_last_accepted_block_id().await.unwrap(),
+            Some(block_id.clone())
         );
-
-        let block = state.get_block(&labid).await
-        .with_context(|| format!("Failed to get Block from database with id {}", labid))
-        .map_err(|e| e.into())
-        .map_err(into_status)?
-        .ok_or_else(||Status::unknown(format!("The storage block with Id {} was not found in the database, which is unusual considering this id was obtained from the database as the last accepted block's id.", labid)))?;
-
-        let u32status = block.status as u32;
-
-        log::trace!(
-            "TimestampVm::Initialize obtained last accepted block with status: {:?}(u32 value: {})",
-            block.status,
-            u32status
+        assert_eq!(
+            state.get_block_id_at_height(1).await.unwrap(),
+            Some(block_id.clone())
         );
-
-        writable_interior.set_preference(labid.clone()).await;
-
-        Ok(Response::new(InitializeResponse {
-            last_accepted_id: Vec::from(labid.as_ref()),
-            last_accepted_parent_id: Vec::from(block.parent_id().as_ref()),
-            bytes: Vec::from(block.data()),
-            height: block.height(),
-            timestamp: Vec::from(block.timestamp().bytes()),
-            status: u32status,
-        }))
+        assert!(matches!(
+            state.get_block(&block_id).await.unwrap().unwrap().status,
+            BlockStatus::Accepted
+        ));
+
+        let ancestors = get_ancestors(
+            &mut vm,
+            &block_id,
+            10,
+            usize::MAX,
+            StdDuration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ancestors.len(), 2);
     }
 
-    async fn bootstrapping(&self, _request: Request<()>) -> Result<Response<()>, Status> {
-        log::trace!("bootstrapping called");
-        Ok(Response::new(()))
-    }
+    #[tokio::test]
+    async fn test_accept_rejects_competing_blocks() {
+        let mut vm = test_vm().await;
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        let mut blocks = Vec::new();
+        for data in [1, 2] {
+            let mut block = Block::new(
+                genesis_id.clone(),
+                1,
+                [data; BLOCK_DATA_LEN],
+                OffsetDateTime::now_utc(),
+                BlockStatus::Processing,
+            )
+            .unwrap();
+            let block_id = block.generate_id().unwrap().clone();
+            vm.verify_block(block.clone()).await.unwrap();
+            blocks.push((block_id, block));
+        }
+        let (competing_id, _) = blocks.pop().unwrap();
+        let (_, accepted) = blocks.pop().unwrap();
 
-    async fn bootstrapped(&self, _request: Request<()>) -> Result<Response<()>, Status> {
-        log::trace!("bootstrapped called");
-        Ok(Response::new(()))
+        vm.accept_block(accepted).await.unwrap();
+        assert!(!vm.processing_blocks.contains(&competing_id));
+        let competing = vm
+            .mut_state()
+            .await
+            .unwrap()
+            .get_block(&competing_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(competing.status, BlockStatus::Rejected));
+
+        // nothing can be built on a rejected block
+        let child = Block::new(
+            competing_id,
+            2,
+            [3; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc(),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        assert!(vm.verify_block(child).await.is_err());
     }
 
-    async fn shutdown(&self, _request: Request<()>) -> Result<Response<()>, Status> {
-        log::trace!("shutdown called");
-        let mut writable_interior = self.interior.write().await;
-        if let Some(state) = writable_interior.state.as_mut() {
-            state.close().await.map_err(into_status)?;
+    // Accepts a chain of blocks on genesis the way an older version did, without a height index
+    async fn accept_unindexed_chain(vm: &mut TimestampVm<MemoryStore>, length: u64) -> Vec<Id> {
+        let state = vm.mut_state().await.unwrap();
+        let mut block_ids = vec![state.get_last_accepted_block_id().await.unwrap().unwrap()];
+        for height in 1..=length {
+            let mut block = Block::new(
+                block_ids.last().unwrap().clone(),
+                height,
+                [1; BLOCK_DATA_LEN],
+                OffsetDateTime::from_unix_timestamp(height as i64).unwrap(),
+                BlockStatus::Accepted,
+            )
+            .unwrap();
+            let block_id = block.generate_id().unwrap().clone();
+            let mut batch = Batch::new();
+            batch.put_block(block).unwrap();
+            batch.set_last_accepted_block_id(&block_id);
+            state.write_batch(batch).await.unwrap();
+            block_ids.push(block_id);
         }
-
-        Ok(Response::new(()))
+        block_ids
     }
 
-    async fn create_handlers(
-        &self,
-        _request: Request<()>,
-    ) -> Result<Response<CreateHandlersResponse>, Status> {
-        log::info!("create_handlers called");
-        let mut writable_interor = self.interior.write().await;
-
-        let ghttp_server = proto::GHttpServer::new_server(
-            writable_interor.grpc_broker.clone(),
-            handlers::new(self.interior.clone()),
-        );
-        log::info!("Creating a new JSON-RPC 2.0 server for API handlers...",);
-        let server_id = writable_interor
-            .new_grpc_server(VM_API_HANDLERS_SERVICE_ID, ghttp_server)
-            .await?;
-
-        let vm_api_service = Handler {
-            prefix: "".to_string(),
-            lock_options: Lock::None as u32,
-            server: server_id,
-        };
-        log::info!(
-            "Created a new API JSON-RPC 2.0 server for handlers with server_id: {}",
-            server_id
-        );
-
-        log::info!("responding with API handler service.",);
-        Ok(Response::new(CreateHandlersResponse {
-            handlers: vec![vm_api_service],
-        }))
+    #[tokio::test]
+    async fn test_index_heights_in_chunks() {
+        let mut vm = test_vm().await;
+        let length = 2 * HEIGHT_INDEX_CHUNK_SIZE + 10;
+        let block_ids = accept_unindexed_chain(&mut vm, length).await;
+
+        vm.index_heights().await.unwrap();
+        let state = vm.mut_state().await.unwrap();
+        for (height, block_id) in block_ids.iter().enumerate() {
+            assert_eq!(
+                state.get_block_id_at_height(height as u64).await.unwrap(),
+                Some(block_id.clone())
+            );
+        }
+        assert_eq!(state.get_height_index_cursor().await.unwrap(), None);
     }
 
-    // This is the code that we must meet: https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm_client.go#L343
-    async fn create_static_handlers(
-        &self,
-        _request: Request<()>,
-    ) -> Result<Response<CreateStaticHandlersResponse>, Status> {
-        log::info!("create_static_handlers called");
-        let mut writable_interior = self.interior.write().await;
+    #[tokio::test]
+    async fn test_index_heights_resumes() {
+        let mut vm = test_vm().await;
+        let block_ids = accept_unindexed_chain(&mut vm, 20).await;
 
-        let ghttp_server = proto::GHttpServer::new_server(
-            writable_interior.grpc_broker.clone(),
-            static_handlers::new(),
-        );
-        log::info!("Creating a new JSON-RPC 2.0 server for static handlers...",);
-        let server_id = writable_interior
-            .new_grpc_server(STATIC_HANDLERS_SERVICE_ID, ghttp_server)
-            .await?;
-
-        let vm_static_api_service = Handler {
-            prefix: "".to_string(),
-            lock_options: Lock::None as u32,
-            server: server_id,
-        };
-        log::info!(
-            "Created a new Static JSON-RPC 2.0 server for static handlers with server_id: {}",
-            server_id
-        );
-
-        log::info!("responding with static API handler service.",);
-        Ok(Response::new(CreateStaticHandlersResponse {
-            handlers: vec![vm_static_api_service],
-        }))
+        // as if a backfill was interrupted after indexing heights 11 to 20
+        let mut batch = Batch::new();
+        for (height, block_id) in block_ids.iter().enumerate().skip(11) {
+            batch.set_block_id_at_height(height as u64, block_id);
+        }
+        batch.set_height_index_cursor(&block_ids[10]);
+        vm.mut_state()
+            .await
+            .unwrap()
+            .write_batch(batch)
+            .await
+            .unwrap();
+
+        vm.index_heights().await.unwrap();
+        let state = vm.mut_state().await.unwrap();
+        for (height, block_id) in block_ids.iter().enumerate() {
+            assert_eq!(
+                state.get_block_id_at_height(height as u64).await.unwrap(),
+                Some(block_id.clone())
+            );
+        }
+        assert_eq!(state.get_height_index_cursor().await.unwrap(), None);
     }
 
-    async fn connected(&self, _request: Request<ConnectedRequest>) -> Result<Response<()>, Status> {
-        log::trace!("connected called");
-        Ok(Response::new(()))
+    #[tokio::test]
+    async fn test_verify_rejects_children_of_old_accepted_blocks() {
+        let mut vm = test_vm().await;
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        let mut blocks = Vec::new();
+        for data in [1, 2] {
+            blocks.push(
+                Block::new(
+                    genesis_id.clone(),
+                    1,
+                    [data; BLOCK_DATA_LEN],
+                    OffsetDateTime::now_utc(),
+                    BlockStatus::Processing,
+                )
+                .unwrap(),
+            );
+        }
+        let mut late = blocks.pop().unwrap();
+        let accepted = blocks.pop().unwrap();
+        vm.verify_block(accepted.clone()).await.unwrap();
+        vm.accept_block(accepted).await.unwrap();
+
+        // genesis is accepted, but no longer last accepted, so nothing on it can be
+        let late_id = late.generate_id().unwrap().clone();
+        assert!(matches!(
+            vm.verify_block(late).await,
+            Err(LandslideError::InvalidBlock { .. })
+        ));
+        assert!(!vm.processing_blocks.contains(&late_id));
     }
 
-    async fn disconnected(
-        &self,
-        _request: Request<DisconnectedRequest>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("disconnected called");
-        Ok(Response::new(()))
+    #[tokio::test]
+    async fn test_decisions_are_final() {
+        let mut vm = test_vm().await;
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        let mut blocks = Vec::new();
+        for data in [1, 2, 3] {
+            let block = Block::new(
+                genesis_id.clone(),
+                1,
+                [data; BLOCK_DATA_LEN],
+                OffsetDateTime::now_utc(),
+                BlockStatus::Processing,
+            )
+            .unwrap();
+            blocks.push(block);
+        }
+        let never_verified = blocks.pop().unwrap();
+        let rejected = blocks.pop().unwrap();
+        let mut accepted = blocks.pop().unwrap();
+        vm.verify_block(accepted.clone()).await.unwrap();
+        vm.verify_block(rejected.clone()).await.unwrap();
+
+        // accepting one rejects the other, so the host rejecting it too changes nothing
+        vm.accept_block(accepted.clone()).await.unwrap();
+        vm.reject_block(rejected.clone()).await.unwrap();
+        vm.accept_block(accepted.clone()).await.unwrap();
+        assert_eq!(vm.metrics.blocks_accepted.get(), 1);
+        assert_eq!(vm.metrics.blocks_rejected.get(), 1);
+
+        // accept after reject
+        assert!(matches!(
+            vm.accept_block(rejected).await,
+            Err(LandslideError::BlockAlreadyDecided {
+                status: BlockStatus::Rejected,
+                ..
+            })
+        ));
+        // reject after accept
+        assert!(matches!(
+            vm.reject_block(accepted.clone()).await,
+            Err(LandslideError::BlockAlreadyDecided {
+                status: BlockStatus::Accepted,
+                ..
+            })
+        ));
+        assert!(matches!(
+            vm.accept_block(never_verified).await,
+            Err(LandslideError::InvalidBlock { .. })
+        ));
+
+        assert_eq!(
+            &vm.last_accepted().await.unwrap(),
+            accepted.generate_id().unwrap()
+        );
     }
 
-    async fn build_block(
-        &self,
-        _request: Request<()>,
-    ) -> Result<Response<BuildBlockResponse>, Status> {
-        log::trace!("build_block called");
-
-        let mut writable_interior = self.interior.write().await;
+    #[tokio::test]
+    async fn test_verify_on_processing_parent() {
+        let mut vm = test_vm().await;
+        let mut parent_id = vm.last_accepted().await.unwrap();
+
+        // a chain of blocks, none of them accepted yet
+        let mut chain = Vec::new();
+        for height in 1..=3 {
+            let mut block = Block::new(
+                parent_id,
+                height,
+                [height as u8; BLOCK_DATA_LEN],
+                OffsetDateTime::now_utc(),
+                BlockStatus::Processing,
+            )
+            .unwrap();
+            parent_id = block.generate_id().unwrap().clone();
+            vm.verify_block(block.clone()).await.unwrap();
+            chain.push(block);
+        }
 
-        // Get the value to put in the new block
-        let block_data = writable_interior
-            .mem_pool
-            .pop()
-            .ok_or_else(|| Status::ok("No blocks to be built."))?;
+        for block in chain {
+            vm.accept_block(block).await.unwrap();
+        }
+        assert_eq!(vm.last_accepted().await.unwrap(), parent_id);
 
-        let preferred_block_id = match writable_interior.preferred_block_id.take() {
-            None => return Err(Status::ok("No preferred block id to be built.")),
-            Some(preferred_block_id) => preferred_block_id,
-        };
+        let orphan = Block::new(
+            Id::new([9; 32]),
+            1,
+            [9; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc(),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        assert!(matches!(
+            vm.verify_block(orphan).await,
+            Err(LandslideError::NoParentBlock { .. })
+        ));
+    }
 
-        // Gets Preferred Block
-        let preferred_block = writable_interior.mut_state().await.map_err(into_status)?
-            .get_block(&preferred_block_id).await.map_err(into_status)?
-            .ok_or_else(||Status::unknown("Preferred block couldn't be retrieved from database, despite having a preferred block id."))?;
-        let preferred_height = preferred_block.height();
+    #[tokio::test]
+    async fn test_verify_rejects_wrong_height() {
+        let mut vm = test_vm().await;
+        let state = vm.mut_state().await.unwrap();
+        let genesis_id = state.get_last_accepted_block_id().await.unwrap().unwrap();
 
-        // Build the block with preferred height
-        let mut block = Block::new(
-            preferred_block_id,
-            preferred_height + 1,
-            block_data,
+        let block = Block::new(
+            genesis_id,
+            2,
+            [2; BLOCK_DATA_LEN],
             OffsetDateTime::now_utc(),
             BlockStatus::Processing,
         )
-        .map_err(into_status)?;
-        writable_interior
-            .verify_block(block.clone())
-            .await
-            .map_err(into_status)?;
-
-        // Notify consensus engine that there are more pending data for blocks
-        // (if that is the case) when done building this block
-        if !writable_interior.mem_pool.is_empty() {
-            writable_interior
-                .notify_block_ready()
-                .await
-                .map_err(into_status)?;
-        }
+        .unwrap();
 
-        Ok(Response::new(BuildBlockResponse {
-            id: block.generate_id().map_err(into_status)?.to_vec(),
-            bytes: Vec::from(block.data()),
-            height: block.height(),
-            parent_id: block.parent_id().to_vec(),
-            timestamp: Vec::from(block.timestamp().bytes()),
-        }))
+        assert!(vm.verify_block(block).await.is_err());
     }
 
-    async fn parse_block(
-        &self,
-        request: Request<ParseBlockRequest>,
-    ) -> Result<Response<ParseBlockResponse>, Status> {
-        log::trace!("parse_block called");
-        let pbr = request.into_inner();
+    #[tokio::test]
+    async fn test_config_limits_future_block_time() {
+        let mut vm = test_vm().await;
+        vm.apply_config(Config::parse(br#"{"max-future-block-time-secs": 60}"#).unwrap());
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        let block = Block::new(
+            genesis_id,
+            1,
+            [2; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc() + Duration::minutes(2),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+
+        assert!(vm.verify_block(block).await.is_err());
+    }
 
-        let mut block: Block = serde_json::from_slice(pbr.bytes.as_ref())
-            .map_err(|e| e.into())
-            .map_err(into_status)?;
+    #[tokio::test]
+    async fn test_strict_timestamps_fork() {
+        let mut vm = test_vm().await;
+        vm.upgrades = Upgrades::parse(br#"{"strict-timestamps": {"height": 2}}"#).unwrap();
+        let genesis_id = vm.last_accepted().await.unwrap();
+        let genesis_time = OffsetDateTime::from_unix_timestamp(0).unwrap();
 
-        block.status = BlockStatus::Processing;
+        // before the fork, a block may share its parent's timestamp...
+        let mut block = Block::new(
+            genesis_id,
+            1,
+            [2; BLOCK_DATA_LEN],
+            genesis_time,
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        let block_id = block.generate_id().unwrap().clone();
+        vm.verify_block(block.clone()).await.unwrap();
+        vm.accept_block(block).await.unwrap();
+
+        // ...but not after it
+        let block = Block::new(
+            block_id,
+            2,
+            [3; BLOCK_DATA_LEN],
+            genesis_time,
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        assert!(vm.verify_block(block).await.is_err());
+    }
 
-        let mut writable_interior = self.interior.write().await;
-        let state = writable_interior.mut_state().await.map_err(into_status)?;
-        let mut ret_block = if let Some(existing_block) = state
-            .get_block(block.generate_id().map_err(into_status)?)
+    #[tokio::test]
+    async fn test_bootstrapping() {
+        let mut vm = test_vm().await;
+        vm.bootstrapping().await.unwrap();
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        assert!(matches!(
+            vm.propose_block(&[1; BLOCK_DATA_LEN]).await,
+            Err(LandslideError::NotInNormalOp { .. })
+        ));
+        vm.app_gossip(b"peer", &gossip::encode(&[1; BLOCK_DATA_LEN]).unwrap())
             .await
-            .map_err(into_status)?
-        {
-            // if we already have this block, return that
-            existing_block
-        } else {
-            block
-        };
+            .unwrap();
+        assert!(vm.mempool.is_empty());
+        assert!(vm.build_block().await.is_err());
+
+        // blocks from far in the future are bootstrapped, since the network accepted them
+        let block = Block::new(
+            genesis_id,
+            1,
+            [2; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc() + Duration::days(1),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        vm.verify_block(block.clone()).await.unwrap();
+        vm.accept_block(block).await.unwrap();
 
-        Ok(Response::new(ParseBlockResponse {
-            id: ret_block.generate_id().map_err(into_status)?.to_vec(),
-            parent_id: ret_block.parent_id().to_vec(),
-            status: ret_block.status as u32,
-            height: ret_block.height(),
-            timestamp: Vec::from(ret_block.timestamp().bytes()),
-        }))
+        vm.bootstrapped().await.unwrap();
+        vm.propose_block(&[1; BLOCK_DATA_LEN]).await.unwrap();
     }
 
-    async fn get_block(
-        &self,
-        _request: Request<GetBlockRequest>,
-    ) -> Result<Response<GetBlockResponse>, Status> {
-        log::trace!("get_block called");
-        todo!()
+    #[tokio::test]
+    async fn test_health_check() {
+        let mut vm = test_vm().await;
+        vm.propose_block(&[1; BLOCK_DATA_LEN]).await.unwrap();
+
+        let report: HealthReport = serde_json::from_str(&vm.health_check().await.unwrap()).unwrap();
+        assert!(report.healthy);
+        assert!(report.state_initialized);
+        assert!(report.database.reachable);
+        assert_eq!(report.mempool.size, 1);
+        assert_eq!(report.connected_peers, 0);
+        // the genesis block is from 1970
+        assert!(report.last_accepted_block_age_secs.unwrap() > 0);
+
+        vm.shutdown().await.unwrap();
+        assert!(matches!(
+            vm.health_check().await,
+            Err(LandslideError::Unhealthy { .. })
+        ));
     }
 
-    async fn set_preference(
-        &self,
-        request: Request<SetPreferenceRequest>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("set_preference called");
-        let spr = request.into_inner();
+    #[tokio::test]
+    async fn test_shutdown() {
+        let mut vm = test_vm().await;
+        vm.propose_block(&[1; BLOCK_DATA_LEN]).await.unwrap();
+        let (_, pending) = vm
+            .network
+            .request(
+                b"peer",
+                &HasBlockRequest {
+                    block_id: ROOT_PARENT_ID,
+                },
+            )
+            .unwrap();
+
+        vm.shutdown().await.unwrap();
+        assert!(vm.shutdown.is_cancelled());
+        assert!(pending.recv().await.is_err());
+
+        // shutting down again is a no-op, while anything else fails
+        vm.shutdown().await.unwrap();
+        assert!(matches!(
+            vm.last_accepted().await,
+            Err(LandslideError::StateNotInitialized)
+        ));
+        assert!(matches!(
+            vm.propose_block(&[2; BLOCK_DATA_LEN]).await,
+            Err(LandslideError::NotInNormalOp { .. })
+        ));
+        assert!(vm.build_block().await.is_err());
+    }
 
-        let mut writable_interior = self.interior.write().await;
-        writable_interior
-            .set_preference(Id::from_slice(&spr.id).map_err(into_status)?)
-            .await;
+    #[tokio::test]
+    async fn test_build_block_keeps_preference() {
+        let mut vm = test_vm().await;
+        for data in 1..=4 {
+            vm.propose_block(&[data; BLOCK_DATA_LEN]).await.unwrap();
+        }
 
-        Ok(Response::new(()))
+        // without a preference, blocks are built on the last accepted block
+        let genesis_id = vm.last_accepted().await.unwrap();
+        let mut first = vm.build_block().await.unwrap();
+        assert_eq!(first.parent_id(), &genesis_id);
+
+        // the preference isn't used up by building
+        let first_id = first.generate_id().unwrap().clone();
+        vm.set_preference(first_id.clone()).await.unwrap();
+        let mut second = vm.build_block().await.unwrap();
+        assert_eq!(second.parent_id(), &first_id);
+        let third = vm.build_block().await.unwrap();
+        assert_eq!(third.parent_id(), &first_id);
+        assert_eq!(third.height(), 2);
+
+        // nothing is built on a rejected block, and the data waits for the next try
+        let second_id = second.generate_id().unwrap().clone();
+        vm.reject_block(second).await.unwrap();
+        vm.set_preference(second_id).await.unwrap();
+        assert!(matches!(
+            vm.build_block().await,
+            Err(LandslideError::PreferredNotBuildable {
+                status: BlockStatus::Rejected,
+                ..
+            })
+        ));
+        assert_eq!(vm.mempool.len(), 2);
+
+        vm.set_preference(Id::new([9; 32])).await.unwrap();
+        assert!(matches!(
+            vm.build_block().await,
+            Err(LandslideError::BlockNotFound { .. })
+        ));
     }
 
-    async fn health(&self, _request: Request<()>) -> Result<Response<HealthResponse>, Status> {
-        log::trace!("health called");
-        log::debug!("TimestampVM: Health endpoint pinged; reporting healthy...");
-        Ok(Response::new(HealthResponse {
-            details: "All is well.".to_string(),
-        }))
-    }
+    #[tokio::test]
+    async fn test_build_block_timestamps_strictly_increase() {
+        let mut vm = test_vm().await;
+        vm.upgrades = Upgrades::parse(br#"{"strict-timestamps": {"height": 0}}"#).unwrap();
+        vm.propose_block(&[1; BLOCK_DATA_LEN]).await.unwrap();
+        vm.propose_block(&[2; BLOCK_DATA_LEN]).await.unwrap();
 
-    async fn version(&self, _request: Request<()>) -> Result<Response<VersionResponse>, Status> {
-        log::trace!("version called");
-        let readable_interior = self.interior.read().await;
-        readable_interior.version().await
-    }
+        // built back to back, well within the same second
+        let mut parent = vm.build_block().await.unwrap();
+        vm.set_preference(parent.generate_id().unwrap().clone())
+            .await
+            .unwrap();
+        let child = vm.build_block().await.unwrap();
 
-    async fn app_request(&self, _request: Request<AppRequestMsg>) -> Result<Response<()>, Status> {
-        log::trace!("app_request called");
-        Ok(Response::new(()))
+        assert!(child.timestamp().offsetdatetime() > parent.timestamp().offsetdatetime());
     }
 
-    async fn app_request_failed(
-        &self,
-        _request: Request<AppRequestFailedMsg>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("app_request_failed called");
-        Ok(Response::new(()))
+    #[tokio::test]
+    async fn test_mempool_survives_restart() {
+        let mut vm = test_vm().await;
+        vm.propose_block(&[1; BLOCK_DATA_LEN]).await.unwrap();
+        vm.propose_block(&[2; BLOCK_DATA_LEN]).await.unwrap();
+        assert!(vm.propose_block(&[1; BLOCK_DATA_LEN]).await.is_err());
+
+        let genesis_id = vm.last_accepted().await.unwrap();
+        vm.set_preference(genesis_id).await.unwrap();
+        let block = vm.build_block().await.unwrap();
+        assert_eq!(block.data(), &[1; BLOCK_DATA_LEN]);
+        vm.accept_block(block).await.unwrap();
+
+        // a new VM on the same state, as after a restart
+        let mut restarted = TimestampVm::with_store(MemoryStore::new()).unwrap();
+        restarted.state = vm.state.take();
+        restarted.load_mempool().await.unwrap();
+        assert_eq!(restarted.mempool.len(), 1);
+        assert_eq!(restarted.mempool.pop(), Some([2; BLOCK_DATA_LEN]));
     }
 
-    async fn app_response(
-        &self,
-        _request: Request<AppResponseMsg>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("app_response called");
-        Ok(Response::new(()))
-    }
+    #[tokio::test]
+    async fn test_app_request_has_block() {
+        let mut vm = test_vm().await;
+        let genesis_id = vm.last_accepted().await.unwrap();
+
+        // the response to a request of our own is routed to whoever awaits it
+        let (msg, pending) = vm
+            .network
+            .request(
+                b"peer",
+                &HasBlockRequest {
+                    block_id: genesis_id.clone(),
+                },
+            )
+            .unwrap();
+
+        // answering it as the peer would
+        let deadline = OffsetDateTime::now_utc() + Duration::seconds(10);
+        let mut peer = test_vm().await;
+        let incoming = peer
+            .network
+            .receive_request(b"us", msg.request_id, deadline, &msg.request)
+            .unwrap()
+            .unwrap();
+        let HasBlockRequest { block_id } = incoming.decode().unwrap();
+        let has_block = peer.get_block(&block_id).await.unwrap().is_some();
+        let response = peer
+            .network
+            .respond::<HasBlockRequest>(&incoming, &HasBlockResponse { has_block })
+            .unwrap()
+            .unwrap();
+
+        vm.app_response(b"peer", response.request_id, &response.response)
+            .await
+            .unwrap();
+        assert_eq!(
+            pending.recv().await.unwrap(),
+            HasBlockResponse { has_block: true }
+        );
 
-    async fn app_gossip(&self, _request: Request<AppGossipMsg>) -> Result<Response<()>, Status> {
-        log::trace!("app_gossip called");
-        Ok(Response::new(()))
+        // and a request that times out fails, but only for the node it was sent to
+        let (msg, pending) = vm
+            .network
+            .request(
+                b"peer",
+                &HasBlockRequest {
+                    block_id: genesis_id,
+                },
+            )
+            .unwrap();
+        vm.app_request_failed(b"other", msg.request_id)
+            .await
+            .unwrap();
+        assert!(vm.network.is_outstanding(msg.request_id));
+        vm.app_request_failed(b"peer", msg.request_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            pending.recv().await,
+            Err(LandslideError::AppRequestFailed { .. })
+        ));
     }
 
-    async fn gather(&self, _request: Request<()>) -> Result<Response<GatherResponse>, Status> {
-        log::trace!("gather called");
-        todo!()
-    }
+    #[tokio::test]
+    async fn test_connected_tracks_peers_but_not_self() {
+        let mut vm = test_vm().await;
+        vm.ctx = Some(Context {
+            node_id: b"self".to_vec(),
+            ..Default::default()
+        });
 
-    async fn block_verify(
-        &self,
-        _request: Request<BlockVerifyRequest>,
-    ) -> Result<Response<BlockVerifyResponse>, Status> {
-        log::trace!("block_verify called");
-        todo!()
-    }
+        vm.connected(b"self", "avalanche/1.7.4").await.unwrap();
+        vm.connected(b"peer", "avalanche/1.7.4").await.unwrap();
+        assert_eq!(vm.peers.len(), 1);
+        assert_eq!(vm.metrics.connected_peers.get(), 1);
 
-    async fn block_accept(
-        &self,
-        _request: Request<BlockAcceptRequest>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("block_accept called");
-        Ok(Response::new(()))
-    }
+        vm.app_gossip(b"peer", b"not a gossip message")
+            .await
+            .unwrap();
+        let (_, peer) = vm.peers.iter().next().unwrap();
+        assert!(peer.last_message_at.is_some());
 
-    async fn block_reject(
-        &self,
-        _request: Request<BlockRejectRequest>,
-    ) -> Result<Response<()>, Status> {
-        log::trace!("block_reject called");
-        Ok(Response::new(()))
+        vm.disconnected(b"peer").await.unwrap();
+        assert_eq!(vm.metrics.connected_peers.get(), 0);
     }
 
-    async fn get_ancestors(
-        &self,
-        _request: Request<GetAncestorsRequest>,
-    ) -> Result<Response<GetAncestorsResponse>, Status> {
-        log::trace!("get_ancestors called");
-        todo!()
-    }
+    #[tokio::test]
+    async fn test_app_gossip_adds_to_mempool_once() {
+        let mut vm = test_vm().await;
+        let msg = gossip::encode(&[5; BLOCK_DATA_LEN]).unwrap();
 
-    async fn batched_parse_block(
-        &self,
-        _request: Request<BatchedParseBlockRequest>,
-    ) -> Result<Response<BatchedParseBlockResponse>, Status> {
-        log::trace!("batched_parse_block called");
-        todo!()
+        vm.app_gossip(b"peer", &msg).await.unwrap();
+        vm.app_gossip(b"peer", &msg).await.unwrap();
+        vm.app_gossip(b"peer", b"not a gossip message")
+            .await
+            .unwrap();
+        assert_eq!(vm.mempool.len(), 1);
+
+        // data accepted in a block built elsewhere isn't brought back by late gossip
+        let genesis_id = vm.last_accepted().await.unwrap();
+        let block = Block::new(
+            genesis_id,
+            1,
+            [6; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc(),
+            BlockStatus::Processing,
+        )
+        .unwrap();
+        vm.verify_block(block.clone()).await.unwrap();
+        vm.accept_block(block).await.unwrap();
+        vm.app_gossip(b"peer", &gossip::encode(&[6; BLOCK_DATA_LEN]).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(vm.mempool.len(), 1);
     }
 }
@@ -0,0 +1,127 @@
+// The peers avalanchego says the VM is connected to, as reported through Connected and Disconnected.
+use std::collections::BTreeMap;
+use time::OffsetDateTime;
+
+// How many peers each gossip message is sent to
+pub const GOSSIP_PEERS: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct Peer {
+    // The avalanchego version the peer reported when it connected
+    pub version: String,
+    pub connected_at: OffsetDateTime,
+    // When the peer last sent an app message (request, response or gossip), if ever
+    pub last_message_at: Option<OffsetDateTime>,
+}
+
+// Targets for gossip and requests are picked round-robin over the peers,
+// so the load spreads over all of them, without needing randomness.
+#[derive(Debug, Default)]
+pub struct Peers {
+    // keyed by node id, ordered so picking round-robin is stable
+    peers: BTreeMap<Vec<u8>, Peer>,
+    next_target: usize,
+}
+
+impl Peers {
+    pub fn new() -> Peers {
+        Peers::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Peer)> {
+        self.peers.iter()
+    }
+
+    // A peer reconnecting starts over, with its newly reported version
+    pub fn connected(&mut self, node_id: &[u8], version: &str) {
+        self.peers.insert(
+            Vec::from(node_id),
+            Peer {
+                version: version.to_string(),
+                connected_at: OffsetDateTime::now_utc(),
+                last_message_at: None,
+            },
+        );
+    }
+
+    // Returns whether the peer was connected at all
+    pub fn disconnected(&mut self, node_id: &[u8]) -> bool {
+        self.peers.remove(node_id).is_some()
+    }
+
+    // Messages from nodes that aren't connected (as far as we know) aren't tracked
+    pub fn observe_message(&mut self, node_id: &[u8]) {
+        if let Some(peer) = self.peers.get_mut(node_id) {
+            peer.last_message_at = Some(OffsetDateTime::now_utc());
+        }
+    }
+
+    // Picks the peer to send the next request to
+    pub fn target(&mut self) -> Option<Vec<u8>> {
+        self.targets(1).pop()
+    }
+
+    // Picks up to count different peers, continuing where the last pick left off
+    pub fn targets(&mut self, count: usize) -> Vec<Vec<u8>> {
+        let count = count.min(self.peers.len());
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let start = self.next_target % self.peers.len();
+        self.next_target = start + count;
+        self.peers
+            .keys()
+            .cycle()
+            .skip(start)
+            .take(count)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_connected_and_disconnected() {
+        let mut peers = Peers::new();
+        peers.connected(b"a", "avalanche/1.7.4");
+        peers.connected(b"b", "avalanche/1.7.4");
+        peers.observe_message(b"a");
+        peers.observe_message(b"unknown");
+        assert_eq!(peers.len(), 2);
+
+        // reconnecting starts over
+        peers.connected(b"a", "avalanche/1.7.5");
+        let (_, a) = peers.iter().next().unwrap();
+        assert_eq!(a.version, "avalanche/1.7.5");
+        assert!(a.last_message_at.is_none());
+
+        assert!(peers.disconnected(b"a"));
+        assert!(!peers.disconnected(b"a"));
+        assert_eq!(peers.len(), 1);
+    }
+
+    #[test]
+    fn test_targets_round_robin() {
+        let mut peers = Peers::new();
+        assert!(peers.targets(1).is_empty());
+        assert_eq!(peers.target(), None);
+
+        peers.connected(b"a", "v");
+        peers.connected(b"b", "v");
+        peers.connected(b"c", "v");
+
+        assert_eq!(peers.targets(2), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(peers.targets(2), vec![b"c".to_vec(), b"a".to_vec()]);
+        assert_eq!(peers.targets(10).len(), 3);
+        assert_eq!(peers.targets(1), vec![b"b".to_vec()]);
+        assert_eq!(peers.target(), Some(b"c".to_vec()));
+    }
+}
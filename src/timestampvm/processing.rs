@@ -0,0 +1,145 @@
+// The blocks that were verified, but not yet accepted or rejected, as a tree.
+// Once a block is accepted, every block competing with it (its siblings, and
+// everything built on them) can never be accepted, and is rejected.
+use super::state::Block;
+use landslide::id::Id;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Default)]
+pub struct ProcessingBlocks {
+    blocks: HashMap<Id, Block>,
+    // parent id -> the processing blocks built on that parent
+    children: HashMap<Id, HashSet<Id>>,
+}
+
+impl ProcessingBlocks {
+    pub fn new() -> ProcessingBlocks {
+        ProcessingBlocks::default()
+    }
+
+    pub fn contains(&self, block_id: &Id) -> bool {
+        self.blocks.contains_key(block_id)
+    }
+
+    pub fn get(&self, block_id: &Id) -> Option<&Block> {
+        self.blocks.get(block_id)
+    }
+
+    pub fn insert(&mut self, block_id: Id, block: Block) {
+        self.children
+            .entry(block.parent_id().clone())
+            .or_default()
+            .insert(block_id.clone());
+        self.blocks.insert(block_id, block);
+    }
+
+    // Forgets a block once decided. Its children (if any) stay, to be decided on their own.
+    pub fn remove(&mut self, block_id: &Id) -> Option<Block> {
+        let block = self.blocks.remove(block_id)?;
+        if let Some(siblings) = self.children.get_mut(block.parent_id()) {
+            siblings.remove(block_id);
+            if siblings.is_empty() {
+                self.children.remove(block.parent_id());
+            }
+        }
+        Some(block)
+    }
+
+    // Forgets an accepted block, and returns every block that conflicts with it:
+    // the other blocks on the same parent and all their descendants, parents before children.
+    // They are left in the tree, for whoever rejects them to remove.
+    pub fn accept(&mut self, block_id: &Id, parent_id: &Id) -> Vec<Block> {
+        self.remove(block_id);
+
+        let mut conflicting = Vec::new();
+        let mut branch: Vec<Id> = self
+            .children
+            .get(parent_id)
+            .map(|siblings| siblings.iter().cloned().collect())
+            .unwrap_or_default();
+        while !branch.is_empty() {
+            let mut next = Vec::new();
+            for id in branch {
+                if let Some(children) = self.children.get(&id) {
+                    next.extend(children.iter().cloned());
+                }
+                if let Some(block) = self.blocks.get(&id) {
+                    conflicting.push(block.clone());
+                }
+            }
+            branch = next;
+        }
+
+        conflicting
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::state::BLOCK_DATA_LEN;
+    use super::*;
+    use landslide::chainvm::Status;
+    use time::OffsetDateTime;
+
+    fn block(parent_id: &Id, height: u64, data: u8) -> (Id, Block) {
+        let mut block = Block::new(
+            parent_id.clone(),
+            height,
+            [data; BLOCK_DATA_LEN],
+            OffsetDateTime::now_utc(),
+            Status::Processing,
+        )
+        .unwrap();
+        (block.generate_id().unwrap().clone(), block)
+    }
+
+    #[test]
+    fn test_accept_rejects_competing_branches() {
+        let root = Id::new([0; 32]);
+        let mut processing = ProcessingBlocks::new();
+
+        // root <- a <- a1
+        //      <- b <- b1 <- b2
+        //      <- c
+        let (a, block_a) = block(&root, 1, 1);
+        let (a1, block_a1) = block(&a, 2, 2);
+        let (b, block_b) = block(&root, 1, 3);
+        let (b1, block_b1) = block(&b, 2, 4);
+        let (b2, block_b2) = block(&b1, 3, 5);
+        let (c, block_c) = block(&root, 1, 6);
+        for (id, block) in [
+            (a.clone(), block_a),
+            (a1.clone(), block_a1),
+            (b.clone(), block_b),
+            (b1.clone(), block_b1),
+            (b2.clone(), block_b2),
+            (c.clone(), block_c),
+        ] {
+            processing.insert(id, block);
+        }
+
+        let mut conflicting: Vec<Id> = processing
+            .accept(&a, &root)
+            .into_iter()
+            .map(|mut block| block.generate_id().unwrap().clone())
+            .collect();
+        // parents come before their children
+        let position = |id: &Id| conflicting.iter().position(|c| c == id).unwrap();
+        assert!(position(&b) < position(&b1) && position(&b1) < position(&b2));
+
+        conflicting.sort_by_key(|id| id.to_vec());
+        let mut expected = vec![b, b1, b2, c];
+        expected.sort_by_key(|id| id.to_vec());
+        assert_eq!(conflicting, expected);
+
+        for id in &expected {
+            processing.remove(id);
+        }
+        // only a's child is left, to be decided on its own
+        assert_eq!(processing.blocks.len(), 1);
+        assert!(processing.contains(&a1));
+        assert!(processing.accept(&a1, &a).is_empty());
+        assert!(processing.blocks.is_empty());
+        assert!(processing.children.is_empty());
+    }
+}
@@ -1,186 +1,339 @@
 // Copied from: https://github.com/ava-labs/timestampvm/blob/main/timestampvm/block.go
 
-use crate::error::LandslideError;
-use crate::id::Id;
-use crate::proto::rpcdb::database_client::*;
-use crate::proto::rpcdb::*;
-use crate::proto::DatabaseError;
-use anyhow::{anyhow, Context, Result};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use bytes::BufMut;
-use lazy_static::lazy_static;
+use super::metrics::Metrics;
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use landslide::chainvm::{self, Status};
+use landslide::codec::{Packer, Unpacker, CODEC_VERSION};
+use landslide::error::LandslideError;
+use landslide::id::Id;
+use landslide::kvstore::{KeyValue, KeyValueStore, KeyValueStream, RpcDb};
+use landslide::timestamp::Timestamp;
 use num::FromPrimitive;
-use serde::{
-    de::{Deserializer, Error},
-    ser::Serializer,
-    Deserialize, Serialize,
-};
+use prometheus::HistogramVec;
+use serde::{Deserialize, Serialize};
 use std::convert::AsRef;
-use std::io::Cursor;
-use time::{format_description::well_known::Rfc3339, OffsetDateTime, UtcOffset};
-use tonic::transport::Channel;
-use tonic::Response;
-
-pub type Db = DatabaseClient<Channel>;
+use time::OffsetDateTime;
 
 const LAST_ACCEPTED_BLOCK_ID_KEY: &[u8] = b"last_accepted_block_id";
 const STATE_INITIALIZED_KEY: &[u8] = b"state_initialized";
+const HEIGHT_INDEX_CURSOR_KEY: &[u8] = b"height_index_cursor";
 const STATE_INITIALIZED_VALUE: &[u8] = b"state_has_infact_been_initialized";
 
 const BLOCK_STATE_PREFIX: &[u8] = b"blockStatePrefix";
+const HEIGHT_INDEX_PREFIX: &[u8] = b"heightIndexPrefix";
+const MEMPOOL_PREFIX: &[u8] = b"mempoolPrefix";
 const SINGLETON_STATE_PREFIX: &[u8] = b"singleton";
 
-// Golang's Zero time is January 1, year 1, 00:00:00.000000000 UTC
-// https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=97
-const GOLANG_ZERO_DATETIME_STR: &str = "0001-01-01T00:00:00Z";
-
 pub const BLOCK_DATA_LEN: usize = 32;
 
 #[derive(Debug)]
-pub struct State {
+pub struct State<S: KeyValueStore = RpcDb> {
     // block database
-    db: Db,
+    store: S,
 
-    last_accepted_block_id_key: Vec<u8>,
-    state_initialized_key: Vec<u8>,
+    db_call_duration: HistogramVec,
 }
 
-impl State {
-    pub fn new(db: Db) -> State {
+impl<S: KeyValueStore> State<S> {
+    pub fn new(store: S, metrics: &Metrics) -> State<S> {
         State {
-            db,
-            last_accepted_block_id_key: Self::prefix(
-                BLOCK_STATE_PREFIX,
-                LAST_ACCEPTED_BLOCK_ID_KEY,
-            ),
-            state_initialized_key: Self::prefix(SINGLETON_STATE_PREFIX, STATE_INITIALIZED_KEY),
+            store,
+            db_call_duration: metrics.db_call_duration.clone(),
         }
     }
 
     // Close closes the underlying base database
-    pub async fn close(&mut self) -> Result<Response<CloseResponse>, LandslideError> {
-        Ok(self.db.close(CloseRequest {}).await?)
+    pub async fn close(&mut self) -> Result<(), LandslideError> {
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["close"])
+            .start_timer();
+        self.store.close().await
     }
 
     pub async fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>, LandslideError> {
-        let get_response = self.db.get(GetRequest { key }).await?.into_inner();
-
-        let dberr = DatabaseError::from_u32(get_response.err);
-        match dberr {
-            Some(DatabaseError::Closed) => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::get returned with error: {:?}",
-                dberr
-            ))),
-            Some(DatabaseError::NotFound) => Ok(None),
-            _ => Ok(Some(get_response.value)),
-        }
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["get"])
+            .start_timer();
+        self.store.get(key).await
     }
 
     pub async fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), LandslideError> {
-        let put_response = self.db.put(PutRequest { key, value }).await?.into_inner();
-
-        let dberr = DatabaseError::from_u32(put_response.err);
-        match dberr {
-            Some(DatabaseError::None) => Ok(()),
-            Some(DatabaseError::Closed) => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::put returned with error: {:?}.",
-                dberr
-            ))),
-            Some(DatabaseError::NotFound) => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::put returned with error: {:?}.",
-                dberr
-            ))),
-            _ => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::put returned with unknown error: {}.",
-                put_response.err
-            ))),
-        }
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["put"])
+            .start_timer();
+        self.store.put(key, value).await
     }
 
     #[allow(dead_code)]
     pub async fn delete(&mut self, key: Vec<u8>) -> Result<(), LandslideError> {
-        let delete_response = self.db.delete(DeleteRequest { key }).await?.into_inner();
-
-        let dberr = DatabaseError::from_u32(delete_response.err);
-        match dberr {
-            Some(DatabaseError::None) => Ok(()),
-            Some(DatabaseError::Closed) => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::delete returned with error: {:?}",
-                dberr
-            ))),
-            Some(DatabaseError::NotFound) => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::delete returned with error: {:?}",
-                dberr
-            ))),
-            _ => Err(LandslideError::Other(anyhow!(
-                "DatabaseClient::delete returned with unknown error: {}.",
-                delete_response.err
-            ))),
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["delete"])
+            .start_timer();
+        self.store.delete(key).await
+    }
+
+    // Commits every write in the batch atomically.
+    // Either all of the batch's puts and deletes are applied, or none of them are.
+    pub async fn write_batch(&mut self, batch: Batch) -> Result<(), LandslideError> {
+        if batch.is_empty() {
+            return Ok(());
         }
+
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["write_batch"])
+            .start_timer();
+        self.store.write_batch(batch.puts, batch.deletes).await
+    }
+
+    // Iterates, in key order, over every key-value pair whose key starts with
+    // prefix and is greater than or equal to start.
+    pub async fn iterate_with_start_and_prefix(
+        &mut self,
+        start: Vec<u8>,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError> {
+        let _timer = self
+            .db_call_duration
+            .with_label_values(&["new_iterator"])
+            .start_timer();
+        self.store
+            .iterate_with_start_and_prefix(start, prefix)
+            .await
+    }
+
+    pub async fn iterate_with_prefix(
+        &mut self,
+        prefix: Vec<u8>,
+    ) -> Result<KeyValueStream, LandslideError> {
+        self.iterate_with_start_and_prefix(Vec::new(), prefix).await
     }
 
     pub async fn get_block(&mut self, block_id: &Id) -> Result<Option<Block>, LandslideError> {
-        let key = Self::prefix(BLOCK_STATE_PREFIX, block_id.as_ref());
+        let key = prefix(BLOCK_STATE_PREFIX, block_id.as_ref());
         let maybe_sb_bytes = self.get(key).await?;
 
         Ok(match maybe_sb_bytes {
-            Some(sb_bytes) => serde_json::from_slice(&sb_bytes)?,
+            Some(sb_bytes) => Some(decode_stored_block(block_id, &sb_bytes)?),
             None => None,
         })
     }
 
-    pub async fn put_block(&mut self, mut block: Block) -> Result<(), LandslideError> {
-        let value = serde_json::to_vec(&block)?;
-        let key = Self::prefix(BLOCK_STATE_PREFIX, block.generate_id()?.as_ref());
-
+    pub async fn put_block(&mut self, block: Block) -> Result<(), LandslideError> {
+        let (key, value) = block_entry(block)?;
         self.put(key, value).await
     }
 
+    // The height index maps the height of every accepted block to that block's Id
+    pub async fn get_block_id_at_height(
+        &mut self,
+        height: u64,
+    ) -> Result<Option<Id>, LandslideError> {
+        match self.get(height_index_key(height)).await? {
+            Some(block_id_bytes) => Ok(Some(Id::from_slice(&block_id_bytes)?)),
+            None => Ok(None),
+        }
+    }
+
     #[allow(dead_code)]
     pub async fn delete_block(&mut self, block_id: &Id) -> Result<(), LandslideError> {
-        let key = Self::prefix(BLOCK_STATE_PREFIX, block_id.as_ref());
+        let key = prefix(BLOCK_STATE_PREFIX, block_id.as_ref());
         self.delete(key).await
     }
 
+    // Where an interrupted height index backfill resumes, if one was interrupted
+    pub async fn get_height_index_cursor(&mut self) -> Result<Option<Id>, LandslideError> {
+        match self.get(height_index_cursor_key()).await? {
+            Some(block_id_bytes) => Ok(Some(Id::from_slice(&block_id_bytes)?)),
+            None => Ok(None),
+        }
+    }
+
     pub async fn get_last_accepted_block_id(&mut self) -> Result<Option<Id>, LandslideError> {
-        match self.get(self.last_accepted_block_id_key.clone()).await? {
+        match self.get(last_accepted_block_id_key()).await? {
             Some(block_id_bytes) => Ok(Some(Id::from_slice(&block_id_bytes)?)),
             None => Ok(None),
         }
     }
 
-    pub async fn set_last_accepted_block_id(&mut self, id: &Id) -> Result<(), LandslideError> {
-        log::info!("Setting last accepted block id bytes: {:?}", id.as_ref());
-        self.put(
-            self.last_accepted_block_id_key.clone(),
-            Vec::from(id.as_ref()),
-        )
-        .await
+    // Every persisted mempool entry, in the order it was proposed
+    pub async fn get_mempool_entries(
+        &mut self,
+    ) -> Result<Vec<(u64, [u8; BLOCK_DATA_LEN])>, LandslideError> {
+        let mut stream = self.iterate_with_prefix(Vec::from(MEMPOOL_PREFIX)).await?;
+
+        let mut entries = Vec::new();
+        while let Some(result) = stream.next().await {
+            let (_, value) = result?;
+            entries.push(decode_mempool_entry(&value)?);
+        }
+        entries.sort_by_key(|(seq, _)| *seq);
+
+        Ok(entries)
     }
 
     pub async fn is_state_initialized(&mut self) -> Result<bool, LandslideError> {
-        let maybe_state_initialized_bytes = self.get(self.state_initialized_key.clone()).await?;
+        let maybe_state_initialized_bytes = self.get(state_initialized_key()).await?;
 
         Ok(match maybe_state_initialized_bytes {
             Some(state_initialized_bytes) => !state_initialized_bytes.is_empty(),
             None => false,
         })
     }
+}
 
-    pub async fn set_state_initialized(&mut self) -> Result<(), LandslideError> {
-        self.put(
-            self.state_initialized_key.clone(),
-            Vec::from(STATE_INITIALIZED_VALUE),
-        )
-        .await
+fn block_entry(mut block: Block) -> Result<KeyValue, LandslideError> {
+    let value = encode_stored_block(&block)?;
+    let key = prefix(BLOCK_STATE_PREFIX, block.generate_id()?.as_ref());
+    Ok((key, value))
+}
+
+// Blocks are stored the way timestampvm's Go blkWrapper is:
+//     [codec version: u16] [block bytes: u32 length + bytes] [status: u32]
+// https://github.com/ava-labs/timestampvm/blob/main/timestampvm/block_state.go
+fn encode_stored_block(block: &Block) -> Result<Vec<u8>, LandslideError> {
+    let mut packer = Packer::with_version(CODEC_VERSION)?;
+    packer.pack_bytes(&block.to_bytes()?)?;
+    packer.pack_int(block.status as u32)?;
+    Ok(packer.into_bytes())
+}
+
+fn decode_stored_block(block_id: &Id, bytes: &[u8]) -> Result<Block, LandslideError> {
+    // Blocks written by older versions of landslide were stored as they were
+    // serde_json encoded, status included.
+    if is_legacy_json(bytes) {
+        log::debug!("Reading legacy JSON-encoded block with id {}", block_id);
+        return Block::from_legacy_json(bytes);
     }
 
-    fn prefix(prefix: &[u8], data: &[u8]) -> Vec<u8> {
-        let mut result = Vec::with_capacity(prefix.len() + data.len());
-        result.extend_from_slice(prefix);
-        result.extend_from_slice(data);
+    let mut unpacker = Unpacker::with_version(bytes, CODEC_VERSION)?;
+    let mut block = Block::from_bytes(unpacker.unpack_bytes()?)?;
+    let status_u32 = unpacker.unpack_int()?;
+    unpacker.done()?;
+
+    block.status = Status::from_u32(status_u32).ok_or_else(|| {
+        LandslideError::Codec(anyhow!(
+            "Unknown status {} for stored block with id {}",
+            status_u32,
+            block_id
+        ))
+    })?;
+
+    Ok(block)
+}
+
+// Mempool entries are keyed by the hash of their data, so they can be deleted given just
+// the data. The sequence number they were proposed in is stored in front of the data:
+//     [sequence number: u64] [data: 32 bytes]
+fn mempool_key(data: &[u8]) -> Vec<u8> {
+    prefix(MEMPOOL_PREFIX, Id::generate(data).as_ref())
+}
+
+fn encode_mempool_entry(seq: u64, data: &[u8; BLOCK_DATA_LEN]) -> Vec<u8> {
+    prefix(&seq.to_be_bytes(), data)
+}
 
-        result
+fn decode_mempool_entry(bytes: &[u8]) -> Result<(u64, [u8; BLOCK_DATA_LEN]), LandslideError> {
+    if bytes.len() != 8 + BLOCK_DATA_LEN {
+        return Err(LandslideError::Codec(anyhow!(
+            "Mempool entry has length {}, expected {}",
+            bytes.len(),
+            8 + BLOCK_DATA_LEN
+        )));
+    }
+
+    let seq = u64::from_be_bytes(bytes[..8].try_into()?);
+    Ok((seq, bytes[8..].try_into()?))
+}
+
+// Big-Endian, so the index keys sort by height
+fn height_index_key(height: u64) -> Vec<u8> {
+    prefix(HEIGHT_INDEX_PREFIX, &height.to_be_bytes())
+}
+
+fn last_accepted_block_id_key() -> Vec<u8> {
+    prefix(BLOCK_STATE_PREFIX, LAST_ACCEPTED_BLOCK_ID_KEY)
+}
+
+fn height_index_cursor_key() -> Vec<u8> {
+    prefix(SINGLETON_STATE_PREFIX, HEIGHT_INDEX_CURSOR_KEY)
+}
+
+fn state_initialized_key() -> Vec<u8> {
+    prefix(SINGLETON_STATE_PREFIX, STATE_INITIALIZED_KEY)
+}
+
+fn prefix(prefix: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(prefix.len() + data.len());
+    result.extend_from_slice(prefix);
+    result.extend_from_slice(data);
+
+    result
+}
+
+// A set of writes that State::write_batch commits atomically.
+// Writes are only staged here; nothing reaches the database until the batch is written.
+#[derive(Debug, Default)]
+pub struct Batch {
+    puts: Vec<KeyValue>,
+    deletes: Vec<Vec<u8>>,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    pub fn put(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.puts.push((key, value));
+    }
+
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.deletes.push(key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puts.is_empty() && self.deletes.is_empty()
+    }
+
+    pub fn put_block(&mut self, block: Block) -> Result<(), LandslideError> {
+        let (key, value) = block_entry(block)?;
+        self.put(key, value);
+        Ok(())
+    }
+
+    pub fn set_block_id_at_height(&mut self, height: u64, block_id: &Id) {
+        self.put(height_index_key(height), block_id.to_vec());
+    }
+
+    pub fn set_last_accepted_block_id(&mut self, id: &Id) {
+        log::info!("Setting last accepted block id bytes: {:?}", id.as_ref());
+        self.put(last_accepted_block_id_key(), Vec::from(id.as_ref()));
+    }
+
+    pub fn set_height_index_cursor(&mut self, block_id: &Id) {
+        self.put(height_index_cursor_key(), block_id.to_vec());
+    }
+
+    pub fn delete_height_index_cursor(&mut self) {
+        self.delete(height_index_cursor_key());
+    }
+
+    pub fn set_state_initialized(&mut self) {
+        self.put(state_initialized_key(), Vec::from(STATE_INITIALIZED_VALUE));
+    }
+
+    pub fn put_mempool_entry(&mut self, seq: u64, data: &[u8; BLOCK_DATA_LEN]) {
+        self.put(mempool_key(data), encode_mempool_entry(seq, data));
+    }
+
+    pub fn delete_mempool_entry(&mut self, data: &[u8]) {
+        self.delete(mempool_key(data));
     }
 }
 
@@ -202,6 +355,12 @@ pub struct Block {
     // Id should be generated, not serialized or deserialized
     #[serde(skip)]
     id: Option<Id>,
+
+    // The serde_json bytes of a block from an older version of landslide. Such a block
+    // keeps both these bytes and its JSON-based Id, so every node still agrees on them,
+    // and parent links to and from it continue to resolve.
+    #[serde(skip)]
+    legacy_json: Option<Vec<u8>>,
 }
 
 impl Block {
@@ -212,6 +371,10 @@ impl Block {
         timestamp: OffsetDateTime,
         status: Status,
     ) -> Result<Self, LandslideError> {
+        // Blocks only carry whole seconds since the Unix epoch (in UTC), so truncate
+        // here to keep the in-memory block identical to the one that gets serialized.
+        let timestamp = OffsetDateTime::from_unix_timestamp(timestamp.unix_timestamp())?;
+
         Ok(Block {
             parent_id,
             height,
@@ -220,9 +383,17 @@ impl Block {
 
             id: None,
             status,
+            legacy_json: None,
         })
     }
 
+    // Reads a block from an older version of landslide, with the status it was encoded with
+    fn from_legacy_json(bytes: &[u8]) -> Result<Self, LandslideError> {
+        let mut block: Block = serde_json::from_slice(bytes)?;
+        block.legacy_json = Some(Vec::from(bytes));
+        Ok(block)
+    }
+
     pub fn parent_id(&self) -> &Id {
         &self.parent_id
     }
@@ -239,291 +410,256 @@ impl Block {
         &self.data
     }
 
+    // Serialize this block into the bytes exchanged with avalanchego,
+    // i.e. the same bytes that Block::from_bytes accepts.
+    // The layout is that of timestampvm's Go Block under avalanchego's linear codec:
+    //     [codec version: u16] [parent id: 32 bytes] [height: u64] [timestamp: i64 unix seconds] [data: 32 bytes]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LandslideError> {
+        if let Some(legacy_json) = &self.legacy_json {
+            return Ok(legacy_json.clone());
+        }
+
+        let mut packer = Packer::with_version(CODEC_VERSION)?;
+        packer.pack_fixed_bytes(self.parent_id.as_ref())?;
+        packer.pack_long(self.height)?;
+        packer.pack_long(self.timestamp.offsetdatetime().unix_timestamp() as u64)?;
+        packer.pack_fixed_bytes(&self.data)?;
+        Ok(packer.into_bytes())
+    }
+
+    // Blocks parsed from bytes are always Processing, until they are found in State.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LandslideError> {
+        if is_legacy_json(bytes) {
+            let mut block = Block::from_legacy_json(bytes)?;
+            block.status = Status::Processing;
+            return Ok(block);
+        }
+
+        let mut unpacker = Unpacker::with_version(bytes, CODEC_VERSION)?;
+        let parent_id = Id::from_slice(unpacker.unpack_fixed_bytes(landslide::id::BYTE_LENGTH)?)?;
+        let height = unpacker.unpack_long()?;
+        let timestamp = OffsetDateTime::from_unix_timestamp(unpacker.unpack_long()? as i64)?;
+        let data: [u8; BLOCK_DATA_LEN] = unpacker.unpack_fixed_bytes(BLOCK_DATA_LEN)?.try_into()?;
+        unpacker.done()?;
+
+        Block::new(parent_id, height, data, timestamp, Status::Processing)
+    }
+
     pub fn generate_id(&mut self) -> Result<&Id, LandslideError> {
         if self.id.is_none() {
-            //generate bytes only for the stuff that makes an identity of the block
-            let mut writer = Vec::new().writer();
-            serde_json::to_writer(&mut writer, &self.parent_id())?;
-            serde_json::to_writer(&mut writer, &self.height())?;
-            serde_json::to_writer(&mut writer, &self.timestamp().bytes())?;
-            serde_json::to_writer(&mut writer, &self.data())?;
-
-            let buf = writer.into_inner();
-            let block_id = Id::generate(&buf);
+            let block_id = self.compute_id()?;
             self.id = Some(block_id);
         }
 
         Ok(self.id.as_ref().expect("in Block::id, the id was just set to Some(_) above and yet is still None. This is next to impossible."))
     }
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-pub enum Status {
-    Unknown,
-    Processing,
-    Rejected,
-    Accepted,
-}
 
-impl Status {
-    #[allow(dead_code)]
-    pub fn fetched(&self) -> bool {
-        match self {
-            Self::Processing => true,
-            _ => self.decided(),
+    fn compute_id(&self) -> Result<Id, LandslideError> {
+        if self.legacy_json.is_none() {
+            // Same as the Go timestampvm: the Id is the SHA256 hash of the block's bytes
+            return Ok(Id::generate(&self.to_bytes()?));
         }
-    }
 
-    #[allow(dead_code)]
-    pub fn decided(&self) -> bool {
-        matches!(self, Self::Rejected | Self::Accepted)
-    }
-
-    #[allow(dead_code)]
-    pub fn valid(&self) -> bool {
-        !matches!(self, Self::Unknown)
+        // Older versions of landslide hashed the JSON of each field that makes
+        // up the block's identity, one after the other
+        let mut buf = Vec::new();
+        serde_json::to_writer(&mut buf, &self.parent_id)?;
+        serde_json::to_writer(&mut buf, &self.height)?;
+        serde_json::to_writer(&mut buf, &self.timestamp.bytes())?;
+        serde_json::to_writer(&mut buf, &self.data)?;
+        Ok(Id::generate(&buf))
     }
 }
 
-// Represents Timestamp as a binary-marshalled array of bytes,
-// or as a Rust-native OffsetDateTime.
-#[derive(Debug, Clone)]
-pub struct Timestamp {
-    bytes: Vec<u8>,
-
-    // only serialize the bytes which are already serialized
-    dt: OffsetDateTime,
+// Binary blocks start with the codec version, so never with a JSON object's brace
+fn is_legacy_json(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&b'{')
 }
 
-impl Serialize for Timestamp {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        serializer.serialize_bytes(self.bytes())
+impl chainvm::Block for Block {
+    fn id(&self) -> Result<Id, LandslideError> {
+        match &self.id {
+            Some(id) => Ok(id.clone()),
+            None => self.compute_id(),
+        }
     }
-}
 
-impl<'de> Deserialize<'de> for Timestamp {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let v = Deserialize::deserialize(deserializer)?;
-        Timestamp::from_bytes(v).map_err(D::Error::custom)
+    fn parent_id(&self) -> &Id {
+        &self.parent_id
     }
-}
 
-impl Timestamp {
-    pub fn from_offsetdatetime(dt: OffsetDateTime) -> Result<Self, LandslideError> {
-        Ok(Timestamp {
-            dt,
-            bytes: Self::offsetdatetime_to_golang_binary_marshal_bytes(dt)?,
-        })
+    fn height(&self) -> u64 {
+        self.height
     }
 
-    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, LandslideError> {
-        Ok(Timestamp {
-            dt: Self::golang_binary_marshal_bytes_to_offsetdatetime(bytes.clone())?,
-            bytes,
-        })
+    fn timestamp(&self) -> &Timestamp {
+        &self.timestamp
     }
 
-    pub fn offsetdatetime(&self) -> &OffsetDateTime {
-        &self.dt
+    fn bytes(&self) -> Result<Vec<u8>, LandslideError> {
+        self.to_bytes()
     }
 
-    pub fn bytes(&self) -> &[u8] {
-        &self.bytes
+    fn status(&self) -> Status {
+        self.status
     }
+}
 
-    // Adapted from: https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=1169
-    // This is HIGHLY UNSTABLE and at the mercy of random go developers whims
-    fn golang_binary_marshal_bytes_to_offsetdatetime(
-        timestamp_bytes: Vec<u8>,
-    ) -> Result<OffsetDateTime, LandslideError> {
-        let mut bytes_reader = Cursor::new(timestamp_bytes);
-
-        let version = bytes_reader.read_u8()
-            .context("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to read the format byte u8 from the timestamp byte vec.")?;
-
-        if version != 1 {
-            return Err(LandslideError::Other(anyhow!("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, did not recognize version {}. We only parse Version 1 of the format.", version)));
-        }
-
-        let golang_secs = bytes_reader.read_i64::<BigEndian>()
-            .context("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to read the BigEndian 64-bit integer seconds from the timestamp byte vec.")?;
-
-        let golang_nanos = bytes_reader.read_i32::<BigEndian>()
-            .context("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to read the BigEndian 32-bit integer nanos from the timestamp byte vec.")?;
-
-        let offset_mins_raw = bytes_reader.read_i16::<BigEndian>()
-            .context("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to read the BigEndian 16-bit integer offset minutes from the timestamp byte vec.")?;
-
-        let offset_mins = match offset_mins_raw {
-            -1 => 0, // if -1 (golang UTC) then convert to 0 (UTF for sane people)
-            of => of,
-        };
-
-        let golang_nanos_whole: i128 = (golang_secs as i128) * 1000000000 + (golang_nanos as i128);
-
-        let unix_timestamp_nanos = Self::nanos_from_unix_epoch(golang_nanos_whole);
-
-        let offset = UtcOffset::from_whole_seconds((offset_mins as i32) * 60)
-            .with_context(|| format!("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to create an offset from minutes: {}", offset_mins))?;
-
-        let dt_without_original_offset = OffsetDateTime::from_unix_timestamp_nanos(unix_timestamp_nanos)
-        .with_context(|| format!("When conveting from Golang's Binary Marshal'd format to an OffsetDateTime, unable to convert unix timestamp nanoseconds {} into an OffsetDateTime", unix_timestamp_nanos))?;
-
-        let dt_with_original_offset = dt_without_original_offset.to_offset(offset);
-
-        Ok(dt_with_original_offset)
+#[cfg(test)]
+mod test {
+    use super::*;
+    use landslide::kvstore::MemoryStore;
+
+    fn test_block() -> Block {
+        Block::new(
+            Id::new([1; 32]),
+            5,
+            [2; BLOCK_DATA_LEN],
+            OffsetDateTime::from_unix_timestamp_nanos(1_000_000_123_456_789).unwrap(),
+            Status::Processing,
+        )
+        .unwrap()
     }
 
-    fn offsetdatetime_to_golang_binary_marshal_bytes(
-        dt: OffsetDateTime,
-    ) -> Result<Vec<u8>, LandslideError> {
-        let offset_secs: i32 = dt.offset().whole_seconds();
-        if offset_secs % 60 != 0 {
-            return Err(LandslideError::Other(anyhow!("When converting OffsetDateTime to a Golang Binary Marshal'd format, offset had fractional minutes which is unsupported.")));
-        }
-
-        let offset_min: i16 = match offset_secs/60 {
-            -1 => return Err(LandslideError::Other(anyhow!("When converting OffsetDateTime to a Golang Binary Marshal'd format, offset of -1 minutes is invalid for Golang Binary Marshaling since it is reserved for UTC. See: https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=1170"))),
-            0 => -1, // if 0 (sane-people UTC), then set -1 (golang UTC)
-            of => i16::try_from(of)
-                .with_context(|| format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to downcast i32 integer {} (the timezone offset in whole seconds) into an i16 integer.", of))?, // Keep the rest as-is
-        };
-
-        let golang_whole_nanos: i128 = Self::nanos_from_golang_zero(dt.unix_timestamp_nanos());
-
-        // remove nanoseconds and cast to i64 as per golang
-        let golang_secs = i64::try_from(golang_whole_nanos/1000000000)
-            .with_context(||format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to downcast i128 integer {} (the seconds part of the whole nanos {}) into an i64 integer.", golang_whole_nanos/1000000000, golang_whole_nanos))?;
-
-        // remove nanoseconds and cast to i32 as per golang
-        let golang_nanos = i32::try_from(golang_whole_nanos%1000000000)
-            .with_context(||format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to downcast i128 integer {} (the nanoseconds part of the whole nanos {}) into an i32 integer.", golang_whole_nanos%1000000000, golang_whole_nanos))?;
-
-        // reserve 15 bytes for now  - 15 lines in the golang link above
-        let mut bytes = Vec::with_capacity(15);
-        bytes.push(1); // byte 0 is version: 1
-
-        // All of this seems to be a bizarre hand-written Big-Endian encoding: https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=1190
-        bytes.write_i64::<BigEndian>(golang_secs)
-            .with_context(|| format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to write 64-bit integer seconds {} to BigEndian", golang_secs))?;
-
-        bytes.write_i32::<BigEndian>(golang_nanos)
-            .with_context(|| format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to write 32-bit integer nanos {} to BigEndian", golang_nanos))?;
+    #[tokio::test]
+    async fn test_block_bytes_layout() {
+        let mut block = test_block();
+        let bytes = block.to_bytes().unwrap();
 
-        bytes.write_i16::<BigEndian>(offset_min)
-            .with_context(|| format!("When converting OffsetDateTime to a Golang Binary Marshal'd format, unable to write 16-bit integer offset minutes {} to BigEndian", offset_min))?;
+        assert_eq!(bytes.len(), 2 + 32 + 8 + 8 + BLOCK_DATA_LEN);
+        assert_eq!(&bytes[0..2], &[0, 0]);
+        assert_eq!(&bytes[2..34], &[1; 32]);
+        assert_eq!(&bytes[34..42], &5u64.to_be_bytes());
+        // sub-second precision is dropped, just as with golang's time.Unix()
+        assert_eq!(&bytes[42..50], &1_000_000i64.to_be_bytes());
+        assert_eq!(&bytes[50..82], &[2; BLOCK_DATA_LEN]);
 
-        Ok(bytes)
+        assert_eq!(block.generate_id().unwrap(), &Id::generate(&bytes));
     }
 
-    fn nanos_from_golang_zero(nanos: i128) -> i128 {
-        lazy_static! {
-            // Golang's Zero time is January 1, year 1, 00:00:00.000000000 UTC
-            // https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=97
-            // init that as an OffsetDateTime for future use
-            static ref GOLANG_ZERO_DATETIME_NANOS_ABS: i128 = OffsetDateTime::parse(GOLANG_ZERO_DATETIME_STR, &Rfc3339)
-                .with_context(|| format!("Unable to parse Golang's Zero DateTime, {}, into a rust OffsetDateTime", GOLANG_ZERO_DATETIME_STR)).unwrap().unix_timestamp_nanos().abs();
-        }
-
-        // Here's the logic.
-        // 1. Incoming nanos are against a Unix Epoch of 0.
-        // 2. Suppose Golang epoch is -10 compared to Unix Epoch.
-        // 3. Suppose incoming nanos are 3, meaning Unix Epoch + 3
-        // 4. We need to conver them into Golang Epoch + <something>
-        // 5. Golang Epoch + <something> = Unix Epoch + 3
-        //     Therefore, <something> = Unix Epoch + 3 - Golang Epoch
-        //      Since we know Golang Epoch is negative (comes before Unix Epoch), and since we know Unix Epoch is "0",
-        //      something = 3 + abs(golang epoch)
-
-        *GOLANG_ZERO_DATETIME_NANOS_ABS + nanos
-    }
-
-    fn nanos_from_unix_epoch(nanos: i128) -> i128 {
-        lazy_static! {
-            // Golang's Zero time is January 1, year 1, 00:00:00.000000000 UTC
-            // https://cs.opensource.google/go/go/+/refs/tags/go1.17.6:src/time/time.go;l=97
-            // init that as an OffsetDateTime for future use
-            static ref GOLANG_ZERO_DATETIME_NANOS_ABS: i128 = OffsetDateTime::parse(GOLANG_ZERO_DATETIME_STR, &Rfc3339)
-                .with_context(|| format!("Unable to parse Golang's Zero DateTime, {}, into a rust OffsetDateTime", GOLANG_ZERO_DATETIME_STR)).unwrap().unix_timestamp_nanos().abs();
-        }
+    #[tokio::test]
+    async fn test_block_bytes_roundtrip() {
+        let mut block = test_block();
+        let mut parsed = Block::from_bytes(&block.to_bytes().unwrap()).unwrap();
 
-        // Here's the logic.
-        // 1. Incoming nanos are against a Golang Epoch of 0.
-        // 2. Suppose Unix epoch is +10 compared to Golang Epoch.
-        // 3. Suppose incoming nanos are 3, meaning Golang Epoch + 3
-        // 4. We need to conver them into Unix Epoch + <something>
-        // 5. Unix Epoch + <something> = Golang Epoch + 3
-        //     Therefore, <something> = Golang Epoch + 3 - Unix Epoch
-        //      Since we know Golang Epoch is negative (comes before Unix Epoch), and since we know Unix Epoch is "0",
-        //      something = 3 + abs(golang epoch)
+        assert_eq!(parsed.to_bytes().unwrap(), block.to_bytes().unwrap());
+        assert_eq!(parsed.generate_id().unwrap(), block.generate_id().unwrap());
+        assert_eq!(
+            parsed.timestamp().offsetdatetime(),
+            block.timestamp().offsetdatetime()
+        );
 
-        nanos - *GOLANG_ZERO_DATETIME_NANOS_ABS
+        let mut trailing = block.to_bytes().unwrap();
+        trailing.push(0);
+        assert!(Block::from_bytes(&trailing).is_err());
     }
 
-    // This function should be used if/when Avalanche resolves this issue:
-    // https://github.com/ava-labs/avalanchego/issues/1003
-    #[allow(dead_code)]
-    pub fn utc8_rfc3339_bytes_to_offsetdatetime(
-        timestamp_bytes: Vec<u8>,
-    ) -> Result<OffsetDateTime, LandslideError> {
-        let rfc_str = String::from_utf8(timestamp_bytes).context(
-            "Unable to parse timestamp as a UTF8 string, which is what the spec expects.",
-        )?;
+    #[tokio::test]
+    async fn test_stored_block_roundtrip() {
+        let mut block = test_block();
+        block.status = Status::Accepted;
+        let block_id = block.generate_id().unwrap().clone();
 
-        Ok(OffsetDateTime::parse(&rfc_str, &Rfc3339)
-            .with_context(|| format!("Failed to parse, what was expected to be an RFC3339 string, into a valid OffsetDateTime: {}", rfc_str))?)
-    }
+        let stored = encode_stored_block(&block).unwrap();
+        let mut decoded = decode_stored_block(&block_id, &stored).unwrap();
 
-    // This function should be used if/when Avalanche resolves this issue:
-    // https://github.com/ava-labs/avalanchego/issues/1003
-    #[allow(dead_code)]
-    pub fn offsetdatetime_to_utc8_rfc3339_bytes(
-        dt: OffsetDateTime,
-    ) -> Result<Vec<u8>, LandslideError> {
-        let rfc_str = dt.format(&Rfc3339).with_context(|| {
-            format!(
-                "Failed to format the OffsetDateTime into an RFC3339 string: {}",
-                dt
-            )
-        })?;
-        Ok(rfc_str.into_bytes())
+        assert!(matches!(decoded.status, Status::Accepted));
+        assert_eq!(decoded.generate_id().unwrap(), &block_id);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[tokio::test]
+    async fn test_legacy_json_stored_block() {
+        let mut block = test_block();
+        block.status = Status::Accepted;
+        let stored = serde_json::to_vec(&block).unwrap();
+
+        // the Id older versions of landslide gave it
+        let mut identity = Vec::new();
+        serde_json::to_writer(&mut identity, block.parent_id()).unwrap();
+        serde_json::to_writer(&mut identity, &block.height()).unwrap();
+        serde_json::to_writer(&mut identity, &block.timestamp().bytes()).unwrap();
+        serde_json::to_writer(&mut identity, &block.data()).unwrap();
+        let legacy_id = Id::generate(&identity);
+
+        let mut decoded = decode_stored_block(&legacy_id, &stored).unwrap();
+        assert_eq!(decoded.generate_id().unwrap(), &legacy_id);
+        assert!(matches!(decoded.status, Status::Accepted));
+        assert_eq!(decoded.height(), block.height());
+        assert_eq!(decoded.data(), block.data());
+
+        // it is served as it was stored, and parses back to the same Id
+        let served = chainvm::Block::bytes(&decoded).unwrap();
+        assert_eq!(served, stored);
+        let mut parsed = Block::from_bytes(&served).unwrap();
+        assert_eq!(parsed.generate_id().unwrap(), &legacy_id);
+        assert!(matches!(parsed.status, Status::Processing));
+
+        // and keeps both once stored again, e.g. with a new status
+        let restored = encode_stored_block(&decoded).unwrap();
+        let mut redecoded = decode_stored_block(&legacy_id, &restored).unwrap();
+        assert_eq!(redecoded.generate_id().unwrap(), &legacy_id);
+        assert_eq!(redecoded.to_bytes().unwrap(), stored);
+    }
 
     #[tokio::test]
-    async fn test_epoch_conversions() {
-        let unix_nanos = 500;
-        let nanos = Timestamp::nanos_from_unix_epoch(Timestamp::nanos_from_golang_zero(unix_nanos));
-        assert_eq!(unix_nanos, nanos);
+    async fn test_height_index_keys_sort_by_height() {
+        let mut keys: Vec<Vec<u8>> = [256u64, 1, 65536, 0, 2]
+            .iter()
+            .map(|h| height_index_key(*h))
+            .collect();
+        keys.sort();
+
+        let expected: Vec<Vec<u8>> = [0u64, 1, 2, 256, 65536]
+            .iter()
+            .map(|h| height_index_key(*h))
+            .collect();
+        assert_eq!(keys, expected);
     }
 
     #[tokio::test]
-    async fn test_dt_conversions() {
-        let dt = OffsetDateTime::now_utc();
-        let newdt = Timestamp::golang_binary_marshal_bytes_to_offsetdatetime(
-            Timestamp::offsetdatetime_to_golang_binary_marshal_bytes(dt).unwrap(),
-        )
-        .unwrap();
-        assert_eq!(dt, newdt);
+    async fn test_batch_stages_accept_writes() {
+        let mut block = test_block();
+        let block_id = block.generate_id().unwrap().clone();
+
+        let mut batch = Batch::new();
+        assert!(batch.is_empty());
+
+        batch.put_block(block.clone()).unwrap();
+        batch.set_block_id_at_height(block.height(), &block_id);
+        batch.set_last_accepted_block_id(&block_id);
+        assert!(!batch.is_empty());
+
+        let keys: Vec<Vec<u8>> = batch.puts.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                prefix(BLOCK_STATE_PREFIX, block_id.as_ref()),
+                height_index_key(block.height()),
+                last_accepted_block_id_key(),
+            ]
+        );
+        assert_eq!(batch.puts[2].1, block_id.to_vec());
+        assert!(batch.deletes.is_empty());
     }
 
     #[tokio::test]
-    async fn test_dt_offset_conversions() {
-        let dt = OffsetDateTime::now_utc().to_offset(UtcOffset::from_whole_seconds(300).unwrap());
-        let newdt = Timestamp::golang_binary_marshal_bytes_to_offsetdatetime(
-            Timestamp::offsetdatetime_to_golang_binary_marshal_bytes(dt).unwrap(),
-        )
-        .unwrap();
-        assert_eq!(dt, newdt);
+    async fn test_mempool_entries_roundtrip() {
+        let metrics = Metrics::new().unwrap();
+        let mut state = State::new(MemoryStore::new(), &metrics);
+
+        let mut batch = Batch::new();
+        batch.put_mempool_entry(9, &[1; BLOCK_DATA_LEN]);
+        batch.put_mempool_entry(3, &[2; BLOCK_DATA_LEN]);
+        batch.put_mempool_entry(5, &[3; BLOCK_DATA_LEN]);
+        state.write_batch(batch).await.unwrap();
+
+        let mut batch = Batch::new();
+        batch.delete_mempool_entry(&[3; BLOCK_DATA_LEN]);
+        state.write_batch(batch).await.unwrap();
+
+        assert_eq!(
+            state.get_mempool_entries().await.unwrap(),
+            vec![(3, [2; BLOCK_DATA_LEN]), (9, [1; BLOCK_DATA_LEN])]
+        );
     }
 }
@@ -1,13 +1,14 @@
-use crate::encoding;
+use super::metrics::Metrics;
 use encoding::{Checksum, Encoding};
 use jsonrpc_core::{BoxFuture, Error as JsonRpcError, IoHandler, Result};
 use jsonrpc_derive::rpc;
+use landslide::encoding;
 use num::FromPrimitive;
 use serde::{Deserialize, Serialize};
 
-pub fn new() -> IoHandler {
+pub fn new(metrics: Metrics) -> IoHandler {
     let mut io = IoHandler::new();
-    let static_handlers = StaticHandlersImpl;
+    let static_handlers = StaticHandlersImpl { metrics };
 
     io.extend_with(static_handlers.to_delegate());
 
@@ -48,10 +49,16 @@ pub trait StaticHandlers {
     fn decode(&self, args: DecodeArgs) -> BoxFuture<Result<DecodeReply>>;
 }
 
-pub struct StaticHandlersImpl;
+pub struct StaticHandlersImpl {
+    metrics: Metrics,
+}
 
 impl StaticHandlers for StaticHandlersImpl {
     fn encode(&self, args: EncodeArgs) -> BoxFuture<Result<EncodeReply>> {
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["encode"])
+            .inc();
         Box::pin(async move {
             log::trace!("Encode called");
             if args.data.is_empty() {
@@ -87,6 +94,10 @@ impl StaticHandlers for StaticHandlersImpl {
     }
 
     fn decode(&self, args: DecodeArgs) -> BoxFuture<Result<DecodeReply>> {
+        self.metrics
+            .jsonrpc_calls
+            .with_label_values(&["decode"])
+            .inc();
         Box::pin(async move {
             log::trace!("Decode called");
 
@@ -134,7 +145,7 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(response, "{\"jsonrpc\":\"2.0\",\"result\":{\"bytes\":\"fP1vxkpyLWnH9dJoiyh\",\"encoding\":0},\"id\":1}");
     }
@@ -151,7 +162,7 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(response, "{\"jsonrpc\":\"2.0\",\"result\":{\"bytes\":\"fP1vxkpyLWnH9dJoiyh\",\"encoding\":0},\"id\":1}");
     }
@@ -170,14 +181,14 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(response, "{\"jsonrpc\":\"2.0\",\"result\":{\"bytes\":\"fP1vxkpyLWnH9dJoiyh\",\"encoding\":0},\"id\":1}");
     }
 
     #[tokio::test]
     async fn test_encode_hex_length() {
-        let io = new();
+        let io = new(Metrics::new().unwrap());
 
         let req_with_foobar = json!({
             "jsonrpc": "2.0",
@@ -222,7 +233,7 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(response, "{\"jsonrpc\":\"2.0\",\"result\":{\"bytes\":\"0x68656c6c6f776f726c64936a185c\",\"encoding\":1},\"id\":1}");
     }
@@ -240,7 +251,7 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(
             response,
@@ -261,7 +272,7 @@ mod test {
         })
         .to_string();
 
-        let io = new();
+        let io = new(Metrics::new().unwrap());
         let response = io.handle_request(&req).await.unwrap();
         assert_eq!(
             response,
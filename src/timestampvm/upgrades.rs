@@ -0,0 +1,89 @@
+// The network upgrade schedule, from the upgrade bytes avalanchego hands to initialize
+// (i.e. the chain's upgrade.json). Each fork changes the rules for every block from the
+// height or time it activates at, so every node switches over at the same block.
+//
+// e.g. {"strict-timestamps": {"height": 1000}} or {"strict-timestamps": {"timestamp": 1650000000}}
+// Forks missing from the schedule never activate. Unknown forks are rejected.
+use anyhow::anyhow;
+use landslide::error::LandslideError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fork {
+    // A block's timestamp must be strictly after its parent's, rather than not before it
+    StrictTimestamps,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Activation {
+    Height(u64),
+    // unix time, in seconds
+    Timestamp(i64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Upgrades {
+    forks: BTreeMap<Fork, Activation>,
+}
+
+impl Upgrades {
+    // No upgrade bytes at all means no forks are scheduled
+    pub fn parse(upgrade_bytes: &[u8]) -> Result<Upgrades, LandslideError> {
+        if upgrade_bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Upgrades::default());
+        }
+
+        serde_json::from_slice(upgrade_bytes).map_err(|err| {
+            LandslideError::Config(anyhow!("Unable to parse the chain's upgrades: {}", err))
+        })
+    }
+
+    pub fn activation(&self, fork: Fork) -> Option<Activation> {
+        self.forks.get(&fork).copied()
+    }
+
+    // Whether the fork's rules apply to a block at this height and unix timestamp
+    pub fn is_active(&self, fork: Fork, height: u64, unix_timestamp: i64) -> bool {
+        match self.activation(fork) {
+            Some(Activation::Height(activation)) => height >= activation,
+            Some(Activation::Timestamp(activation)) => unix_timestamp >= activation,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Upgrades::parse(b"").unwrap(), Upgrades::default());
+        assert!(!Upgrades::default().is_active(Fork::StrictTimestamps, u64::MAX, i64::MAX));
+
+        let upgrades = Upgrades::parse(br#"{"strict-timestamps": {"height": 10}}"#).unwrap();
+        assert_eq!(
+            upgrades.activation(Fork::StrictTimestamps),
+            Some(Activation::Height(10))
+        );
+
+        assert!(Upgrades::parse(br#"{"no-such-fork": {"height": 10}}"#).is_err());
+        assert!(Upgrades::parse(br#"{"strict-timestamps": {"block": 10}}"#).is_err());
+        assert!(Upgrades::parse(br#"{"strict-timestamps": 10}"#).is_err());
+    }
+
+    #[test]
+    fn test_is_active() {
+        let by_height = Upgrades::parse(br#"{"strict-timestamps": {"height": 10}}"#).unwrap();
+        assert!(!by_height.is_active(Fork::StrictTimestamps, 9, i64::MAX));
+        assert!(by_height.is_active(Fork::StrictTimestamps, 10, 0));
+
+        let by_time = Upgrades::parse(br#"{"strict-timestamps": {"timestamp": 1000}}"#).unwrap();
+        assert!(!by_time.is_active(Fork::StrictTimestamps, u64::MAX, 999));
+        assert!(by_time.is_active(Fork::StrictTimestamps, 0, 1000));
+    }
+}
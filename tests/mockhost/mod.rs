@@ -0,0 +1,768 @@
+// A mock avalanchego host, for driving the landslide plugin end to end in tests.
+//
+// It starts the plugin binary the way avalanchego does (go-plugin handshake over stdout),
+// serves every host-side service the VM dials during Initialize (an in-memory rpcdb
+// Database, Messenger, AppSender, Keystore, SharedMemory, AliasReader and SubnetLookup),
+// and brokers all of their connections through the plugin's GRPCBroker.
+// Nothing leaves the machine: every connection is a unix socket.
+// Only part of each protocol is used by the tests
+#[allow(dead_code)]
+pub mod proto {
+    pub mod vm_proto {
+        tonic::include_proto!("vmproto");
+    }
+
+    pub mod io {
+        pub mod prometheus {
+            pub mod client {
+                tonic::include_proto!("io.prometheus.client");
+            }
+        }
+    }
+
+    pub mod plugin {
+        tonic::include_proto!("plugin");
+    }
+
+    pub mod appsender {
+        tonic::include_proto!("appsenderproto");
+    }
+
+    pub mod galiasreader {
+        tonic::include_proto!("galiasreaderproto");
+    }
+
+    pub mod ghttp {
+        tonic::include_proto!("ghttpproto");
+    }
+
+    pub mod gkeystore {
+        tonic::include_proto!("gkeystoreproto");
+    }
+
+    pub mod gsharedmemory {
+        tonic::include_proto!("gsharedmemoryproto");
+    }
+
+    pub mod gsubnetlookup {
+        tonic::include_proto!("gsubnetlookupproto");
+    }
+
+    pub mod messenger {
+        tonic::include_proto!("messengerproto");
+    }
+
+    pub mod rpcdb {
+        tonic::include_proto!("rpcdbproto");
+    }
+
+    pub mod greadcloser {
+        tonic::include_proto!("greadcloserproto");
+    }
+
+    pub mod gresponsewriter {
+        tonic::include_proto!("gresponsewriterproto");
+    }
+}
+
+use futures::StreamExt;
+use grr_plugin::unix::{incoming_from_path, TempSocket};
+use hyper::{Body, Request as HyperRequest, Response as HyperResponse};
+use proto::appsender::app_sender_server::{AppSender, AppSenderServer};
+use proto::appsender::*;
+use proto::galiasreader::alias_reader_server::{AliasReader, AliasReaderServer};
+use proto::galiasreader::*;
+use proto::ghttp::http_client::HttpClient;
+use proto::ghttp::{HttpRequest, Request as GHttpRequest, ResponseWriter};
+use proto::gkeystore::keystore_server::{Keystore, KeystoreServer};
+use proto::gkeystore::*;
+use proto::greadcloser::reader_server::{Reader, ReaderServer};
+use proto::gresponsewriter::writer_server::{Writer, WriterServer};
+use proto::gresponsewriter::*;
+use proto::gsharedmemory::shared_memory_server::{SharedMemory, SharedMemoryServer};
+use proto::gsubnetlookup::subnet_lookup_server::{SubnetLookup, SubnetLookupServer};
+use proto::gsubnetlookup::*;
+use proto::messenger::messenger_server::{Messenger, MessengerServer};
+use proto::messenger::*;
+use proto::plugin::grpc_broker_client::GrpcBrokerClient;
+use proto::plugin::ConnInfo;
+use proto::rpcdb::database_server::{Database, DatabaseServer};
+use proto::rpcdb::*;
+use proto::vm_proto::vm_client::VmClient;
+use proto::vm_proto::*;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error as StdError;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Mutex;
+use tonic::body::BoxBody;
+use tonic::transport::{Channel, Endpoint, NamedService, Server, Uri};
+use tonic::{Request, Response, Status};
+use tower::{service_fn, Service};
+
+// https://github.com/ava-labs/avalanchego/blob/master/vms/rpcchainvm/vm.go#L19
+const AVALANCHE_VM_PROTOCOL_VERSION: &str = "9";
+
+// https://github.com/ava-labs/avalanchego/blob/master/database/rpcdb/errors.go
+const DATABASE_ERROR_NONE: u32 = 0;
+const DATABASE_ERROR_NOT_FOUND: u32 = 2;
+
+// How long to wait for the plugin to announce a service through the broker
+const PLUGIN_SERVICE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct MockHost {
+    plugin: Child,
+    _plugin_stdout: BufReader<ChildStdout>,
+
+    // The sockets the host-side services listen on. Dropping one deletes its socket.
+    sockets: Vec<TempSocket>,
+    next_service_id: u32,
+
+    // ConnInfo's of host-side services, streamed to the plugin's broker
+    host_conninfo_sender: UnboundedSender<ConnInfo>,
+    // ConnInfo's of plugin-side services (such as JSON-RPC handlers), streamed from the plugin's broker
+    plugin_services: Arc<Mutex<HashMap<u32, ConnInfo>>>,
+
+    pub vm: VmClient<Channel>,
+    pub db: MockDatabase,
+
+    // Every message the VM sent to the consensus engine through the Messenger
+    pub engine_messages: UnboundedReceiver<u32>,
+    engine_message_sender: UnboundedSender<u32>,
+
+    // Every message the VM gossiped to peers through the AppSender
+    pub gossip_messages: UnboundedReceiver<Vec<u8>>,
+    gossip_message_sender: UnboundedSender<Vec<u8>>,
+
+    // Every request the VM sent to peers through the AppSender
+    pub app_requests: UnboundedReceiver<SendAppRequestMsg>,
+    app_request_sender: UnboundedSender<SendAppRequestMsg>,
+    // What peers answer those requests with, if anything (see answer_app_requests)
+    app_response: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl MockHost {
+    // Starts the plugin, completes the go-plugin handshake and opens the broker stream
+    pub async fn start() -> MockHost {
+        let mut plugin = Command::new(env!("CARGO_BIN_EXE_landslide"))
+            .env("VM_PLUGIN", "dynamic")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Unable to start the landslide plugin");
+
+        let stdout = plugin.stdout.take().expect("plugin stdout was not piped");
+        let (handshake, plugin_stdout) = tokio::task::spawn_blocking(move || {
+            let mut reader = BufReader::new(stdout);
+            let mut handshake = String::new();
+            reader
+                .read_line(&mut handshake)
+                .expect("Unable to read the go-plugin handshake from the plugin");
+            (handshake, reader)
+        })
+        .await
+        .unwrap();
+
+        // core protocol version|app protocol version|network|address|protocol
+        let parts: Vec<&str> = handshake.trim().split('|').collect();
+        assert_eq!(parts.len(), 6, "Unexpected handshake: {}", handshake);
+        assert_eq!(parts[1], AVALANCHE_VM_PROTOCOL_VERSION);
+        assert_eq!(parts[2], "unix");
+        assert_eq!(parts[4], "grpc");
+        let channel = dial_unix(parts[3].to_string()).await;
+
+        let (host_conninfo_sender, mut host_conninfo_receiver) = unbounded_channel::<ConnInfo>();
+        let outgoing = async_stream::stream! {
+            while let Some(conn_info) = host_conninfo_receiver.recv().await {
+                yield conn_info;
+            }
+        };
+        let mut incoming = GrpcBrokerClient::new(channel.clone())
+            .start_stream(Request::new(outgoing))
+            .await
+            .expect("Unable to start the broker stream with the plugin")
+            .into_inner();
+
+        let plugin_services = Arc::new(Mutex::new(HashMap::new()));
+        let plugin_services_for_stream = plugin_services.clone();
+        tokio::spawn(async move {
+            while let Some(Ok(conn_info)) = incoming.next().await {
+                plugin_services_for_stream
+                    .lock()
+                    .await
+                    .insert(conn_info.service_id, conn_info);
+            }
+        });
+
+        let (engine_message_sender, engine_messages) = unbounded_channel();
+        let (gossip_message_sender, gossip_messages) = unbounded_channel();
+        let (app_request_sender, app_requests) = unbounded_channel();
+
+        MockHost {
+            plugin,
+            _plugin_stdout: plugin_stdout,
+            sockets: Vec::new(),
+            // Well clear of the ids the plugin picks for its own services
+            next_service_id: 100,
+            host_conninfo_sender,
+            plugin_services,
+            vm: VmClient::new(channel),
+            db: MockDatabase::default(),
+            engine_messages,
+            engine_message_sender,
+            gossip_messages,
+            gossip_message_sender,
+            app_requests,
+            app_request_sender,
+            app_response: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    // Serves a host-side service on a fresh unix socket, and tells the plugin's broker about it.
+    pub async fn serve<S>(&mut self, service: S) -> u32
+    where
+        S: Service<HyperRequest<Body>, Response = HyperResponse<BoxBody>>
+            + NamedService
+            + Clone
+            + Send
+            + 'static,
+        <S as Service<HyperRequest<Body>>>::Future: Send + 'static,
+        <S as Service<HyperRequest<Body>>>::Error: Into<Box<dyn StdError + Send + Sync>> + Send,
+    {
+        let socket = TempSocket::new().unwrap();
+        let address = socket.socket_filename().unwrap();
+        let incoming = incoming_from_path(&address).await.unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(service)
+                .serve_with_incoming(incoming),
+        );
+        self.sockets.push(socket);
+
+        let service_id = self.next_service_id;
+        self.next_service_id += 1;
+        self.host_conninfo_sender
+            .send(ConnInfo {
+                service_id,
+                network: "unix".to_string(),
+                address,
+            })
+            .unwrap();
+
+        service_id
+    }
+
+    // Dials a service the plugin announced through its broker
+    pub async fn dial_plugin_service(&self, service_id: u32) -> Channel {
+        let conn_info = tokio::time::timeout(PLUGIN_SERVICE_TIMEOUT, async {
+            loop {
+                if let Some(conn_info) = self.plugin_services.lock().await.get(&service_id) {
+                    return conn_info.clone();
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("The plugin never announced service {}", service_id));
+
+        assert_eq!(conn_info.network, "unix");
+        dial_unix(conn_info.address).await
+    }
+
+    // Serves every host-side service and calls Initialize with them
+    pub async fn initialize(&mut self, genesis_bytes: &[u8]) -> InitializeResponse {
+        let db_server = self.serve(DatabaseServer::new(self.db.clone())).await;
+        let engine_server = self
+            .serve(MessengerServer::new(MockMessenger {
+                sender: self.engine_message_sender.clone(),
+            }))
+            .await;
+        let keystore_server = self.serve(KeystoreServer::new(MockKeystore)).await;
+        let shared_memory_server = self.serve(SharedMemoryServer::new(MockSharedMemory)).await;
+        let bc_lookup_server = self.serve(AliasReaderServer::new(MockAliasReader)).await;
+        let sn_lookup_server = self.serve(SubnetLookupServer::new(MockSubnetLookup)).await;
+        let app_sender_server = self
+            .serve(AppSenderServer::new(MockAppSender {
+                gossip_sender: self.gossip_message_sender.clone(),
+                request_sender: self.app_request_sender.clone(),
+                response: self.app_response.clone(),
+                vm: self.vm.clone(),
+            }))
+            .await;
+
+        self.vm
+            .initialize(InitializeRequest {
+                network_id: 12345,
+                subnet_id: vec![0; 32],
+                chain_id: vec![1; 32],
+                node_id: vec![2; 20],
+                x_chain_id: vec![3; 32],
+                avax_asset_id: vec![4; 32],
+                genesis_bytes: genesis_bytes.to_vec(),
+                upgrade_bytes: vec![],
+                config_bytes: vec![],
+                db_servers: vec![VersionedDbServer {
+                    db_server,
+                    version: "v1.4.5".to_string(),
+                }],
+                engine_server,
+                keystore_server,
+                shared_memory_server,
+                bc_lookup_server,
+                sn_lookup_server,
+                app_sender_server,
+            })
+            .await
+            .expect("Initialize failed")
+            .into_inner()
+    }
+
+    // From now on, every peer the VM sends a request to answers it with response
+    pub async fn answer_app_requests(&self, response: Vec<u8>) {
+        *self.app_response.lock().await = Some(response);
+    }
+
+    // Calls a JSON-RPC method on a handler the VM returned from CreateHandlers,
+    // the way avalanchego's ghttp client forwards an HTTP request to it.
+    pub async fn call_jsonrpc(
+        &mut self,
+        handler_server: u32,
+        method: &str,
+        params: Value,
+    ) -> Value {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": 1,
+        });
+
+        let reader_server = self
+            .serve(ReaderServer::new(MockReader {
+                body: body.to_string().into_bytes(),
+            }))
+            .await;
+        let writer = MockWriter::default();
+        let written = writer.written.clone();
+        let writer_server = self.serve(WriterServer::new(writer)).await;
+
+        let mut http = HttpClient::new(self.dial_plugin_service(handler_server).await);
+        http.handle(HttpRequest {
+            response_writer: Some(ResponseWriter {
+                id: writer_server,
+                header: vec![],
+            }),
+            request: Some(GHttpRequest {
+                method: "POST".to_string(),
+                body: reader_server,
+                ..Default::default()
+            }),
+        })
+        .await
+        .expect("Handle failed");
+
+        let written = written.lock().await;
+        serde_json::from_slice(&written).expect("The handler wrote a non-JSON response")
+    }
+}
+
+impl Drop for MockHost {
+    fn drop(&mut self) {
+        let _ = self.plugin.kill();
+        let _ = self.plugin.wait();
+    }
+}
+
+async fn dial_unix(address: String) -> Channel {
+    // Copied from: https://github.com/hyperium/tonic/blob/master/examples/src/uds/client.rs
+    Endpoint::try_from("http://[::]:50051")
+        .unwrap()
+        .connect_with_connector(service_fn(move |_: Uri| {
+            UnixStream::connect(address.clone())
+        }))
+        .await
+        .expect("Unable to dial a unix socket")
+}
+
+// An in-memory rpcdb Database
+#[derive(Clone, Default)]
+pub struct MockDatabase {
+    entries: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
+    // Remaining pairs of each open iterator, by iterator id
+    iterators: Arc<Mutex<HashMap<u64, Vec<PutRequest>>>>,
+    next_iterator_id: Arc<Mutex<u64>>,
+}
+
+impl MockDatabase {
+    pub async fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.entries.lock().await.get(key).cloned()
+    }
+}
+
+#[tonic::async_trait]
+impl Database for MockDatabase {
+    async fn has(&self, request: Request<HasRequest>) -> Result<Response<HasResponse>, Status> {
+        let has = self
+            .entries
+            .lock()
+            .await
+            .contains_key(&request.into_inner().key);
+        Ok(Response::new(HasResponse {
+            has,
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        Ok(Response::new(
+            match self.entries.lock().await.get(&request.into_inner().key) {
+                Some(value) => GetResponse {
+                    value: value.clone(),
+                    err: DATABASE_ERROR_NONE,
+                },
+                None => GetResponse {
+                    value: vec![],
+                    err: DATABASE_ERROR_NOT_FOUND,
+                },
+            },
+        ))
+    }
+
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let put = request.into_inner();
+        self.entries.lock().await.insert(put.key, put.value);
+        Ok(Response::new(PutResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        self.entries.lock().await.remove(&request.into_inner().key);
+        Ok(Response::new(DeleteResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn stat(&self, _request: Request<StatRequest>) -> Result<Response<StatResponse>, Status> {
+        Err(Status::unimplemented("stat"))
+    }
+
+    async fn compact(
+        &self,
+        _request: Request<CompactRequest>,
+    ) -> Result<Response<CompactResponse>, Status> {
+        Ok(Response::new(CompactResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn close(
+        &self,
+        _request: Request<CloseRequest>,
+    ) -> Result<Response<CloseResponse>, Status> {
+        Ok(Response::new(CloseResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    // Batches the VM splits across several requests aren't supported.
+    async fn write_batch(
+        &self,
+        request: Request<WriteBatchRequest>,
+    ) -> Result<Response<WriteBatchResponse>, Status> {
+        let batch = request.into_inner();
+        if batch.continues {
+            return Err(Status::unimplemented("write_batch with continues"));
+        }
+
+        let mut entries = self.entries.lock().await;
+        for put in batch.puts {
+            entries.insert(put.key, put.value);
+        }
+        for delete in batch.deletes {
+            entries.remove(&delete.key);
+        }
+
+        Ok(Response::new(WriteBatchResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn new_iterator_with_start_and_prefix(
+        &self,
+        request: Request<NewIteratorWithStartAndPrefixRequest>,
+    ) -> Result<Response<NewIteratorWithStartAndPrefixResponse>, Status> {
+        let request = request.into_inner();
+        let start = request.start.max(request.prefix.clone());
+        let pairs = self
+            .entries
+            .lock()
+            .await
+            .range(start..)
+            .take_while(|(key, _)| key.starts_with(&request.prefix))
+            .map(|(key, value)| PutRequest {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+
+        let mut next_iterator_id = self.next_iterator_id.lock().await;
+        let id = *next_iterator_id;
+        *next_iterator_id += 1;
+        self.iterators.lock().await.insert(id, pairs);
+
+        Ok(Response::new(NewIteratorWithStartAndPrefixResponse { id }))
+    }
+
+    // Everything that's left is returned in one page.
+    async fn iterator_next(
+        &self,
+        request: Request<IteratorNextRequest>,
+    ) -> Result<Response<IteratorNextResponse>, Status> {
+        let id = request.into_inner().id;
+        let data = match self.iterators.lock().await.get_mut(&id) {
+            Some(pairs) => std::mem::take(pairs),
+            None => return Err(Status::not_found(format!("No iterator with id {}", id))),
+        };
+        Ok(Response::new(IteratorNextResponse { data }))
+    }
+
+    async fn iterator_error(
+        &self,
+        _request: Request<IteratorErrorRequest>,
+    ) -> Result<Response<IteratorErrorResponse>, Status> {
+        Ok(Response::new(IteratorErrorResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+
+    async fn iterator_release(
+        &self,
+        request: Request<IteratorReleaseRequest>,
+    ) -> Result<Response<IteratorReleaseResponse>, Status> {
+        self.iterators.lock().await.remove(&request.into_inner().id);
+        Ok(Response::new(IteratorReleaseResponse {
+            err: DATABASE_ERROR_NONE,
+        }))
+    }
+}
+
+// Forwards every message the VM sends the consensus engine to MockHost::engine_messages
+struct MockMessenger {
+    sender: UnboundedSender<u32>,
+}
+
+#[tonic::async_trait]
+impl Messenger for MockMessenger {
+    async fn notify(
+        &self,
+        request: Request<NotifyRequest>,
+    ) -> Result<Response<NotifyResponse>, Status> {
+        let _ = self.sender.send(request.into_inner().message);
+        Ok(Response::new(NotifyResponse {}))
+    }
+}
+
+// Stands in for the network. Gossip is forwarded to MockHost::gossip_messages and requests
+// to MockHost::app_requests, so tests can see what would have been sent. Requests are
+// answered as set with MockHost::answer_app_requests, and otherwise left unanswered.
+struct MockAppSender {
+    gossip_sender: UnboundedSender<Vec<u8>>,
+    request_sender: UnboundedSender<SendAppRequestMsg>,
+    response: Arc<Mutex<Option<Vec<u8>>>>,
+    vm: VmClient<Channel>,
+}
+
+#[tonic::async_trait]
+impl AppSender for MockAppSender {
+    async fn send_app_request(
+        &self,
+        request: Request<SendAppRequestMsg>,
+    ) -> Result<Response<()>, Status> {
+        let request = request.into_inner();
+        let _ = self.request_sender.send(request.clone());
+
+        // Answered separately, as a peer would: the VM may be busy until this returns
+        if let Some(response) = self.response.lock().await.clone() {
+            let mut vm = self.vm.clone();
+            tokio::spawn(async move {
+                for node_id in request.node_i_ds {
+                    let _ = vm
+                        .app_response(AppResponseMsg {
+                            node_id,
+                            request_id: request.request_id,
+                            response: response.clone(),
+                        })
+                        .await;
+                }
+            });
+        }
+
+        Ok(Response::new(()))
+    }
+
+    async fn send_app_response(
+        &self,
+        _request: Request<SendAppResponseMsg>,
+    ) -> Result<Response<()>, Status> {
+        Ok(Response::new(()))
+    }
+
+    async fn send_app_gossip(
+        &self,
+        request: Request<SendAppGossipMsg>,
+    ) -> Result<Response<()>, Status> {
+        let _ = self.gossip_sender.send(request.into_inner().msg);
+        Ok(Response::new(()))
+    }
+
+    async fn send_app_gossip_specific(
+        &self,
+        _request: Request<SendAppGossipSpecificMsg>,
+    ) -> Result<Response<()>, Status> {
+        Ok(Response::new(()))
+    }
+}
+
+// The timestampvm doesn't use these, so they only need to be dialable.
+struct MockKeystore;
+
+#[tonic::async_trait]
+impl Keystore for MockKeystore {
+    async fn get_database(
+        &self,
+        _request: Request<GetDatabaseRequest>,
+    ) -> Result<Response<GetDatabaseResponse>, Status> {
+        Err(Status::unimplemented("get_database"))
+    }
+}
+
+struct MockSharedMemory;
+
+#[tonic::async_trait]
+impl SharedMemory for MockSharedMemory {
+    async fn get(
+        &self,
+        _request: Request<proto::gsharedmemory::GetRequest>,
+    ) -> Result<Response<proto::gsharedmemory::GetResponse>, Status> {
+        Err(Status::unimplemented("get"))
+    }
+
+    async fn indexed(
+        &self,
+        _request: Request<proto::gsharedmemory::IndexedRequest>,
+    ) -> Result<Response<proto::gsharedmemory::IndexedResponse>, Status> {
+        Err(Status::unimplemented("indexed"))
+    }
+
+    async fn apply(
+        &self,
+        _request: Request<proto::gsharedmemory::ApplyRequest>,
+    ) -> Result<Response<proto::gsharedmemory::ApplyResponse>, Status> {
+        Err(Status::unimplemented("apply"))
+    }
+}
+
+struct MockAliasReader;
+
+#[tonic::async_trait]
+impl AliasReader for MockAliasReader {
+    async fn lookup(&self, _request: Request<Alias>) -> Result<Response<Id>, Status> {
+        Err(Status::unimplemented("lookup"))
+    }
+
+    async fn primary_alias(&self, _request: Request<Id>) -> Result<Response<Alias>, Status> {
+        Err(Status::unimplemented("primary_alias"))
+    }
+
+    async fn aliases(&self, _request: Request<Id>) -> Result<Response<AliasList>, Status> {
+        Err(Status::unimplemented("aliases"))
+    }
+}
+
+struct MockSubnetLookup;
+
+#[tonic::async_trait]
+impl SubnetLookup for MockSubnetLookup {
+    async fn subnet_id(
+        &self,
+        _request: Request<SubnetIdRequest>,
+    ) -> Result<Response<SubnetIdResponse>, Status> {
+        Err(Status::unimplemented("subnet_id"))
+    }
+}
+
+// The body of a single HTTP request, read all at once
+struct MockReader {
+    body: Vec<u8>,
+}
+
+#[tonic::async_trait]
+impl Reader for MockReader {
+    async fn read(
+        &self,
+        _request: Request<proto::greadcloser::ReadRequest>,
+    ) -> Result<Response<proto::greadcloser::ReadResponse>, Status> {
+        Ok(Response::new(proto::greadcloser::ReadResponse {
+            read: self.body.clone(),
+            error: "EOF".to_string(),
+            errored: true,
+        }))
+    }
+
+    async fn close(
+        &self,
+        _request: Request<proto::greadcloser::CloseRequest>,
+    ) -> Result<Response<proto::greadcloser::CloseResponse>, Status> {
+        Ok(Response::new(proto::greadcloser::CloseResponse {}))
+    }
+}
+
+// Collects the body of a single HTTP response
+#[derive(Default)]
+struct MockWriter {
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+#[tonic::async_trait]
+impl Writer for MockWriter {
+    async fn write(
+        &self,
+        request: Request<WriteRequest>,
+    ) -> Result<Response<WriteResponse>, Status> {
+        let payload = request.into_inner().payload;
+        let written = payload.len() as i32;
+        self.written.lock().await.extend(payload);
+        Ok(Response::new(WriteResponse { written }))
+    }
+
+    async fn write_header(
+        &self,
+        _request: Request<WriteHeaderRequest>,
+    ) -> Result<Response<WriteHeaderResponse>, Status> {
+        Ok(Response::new(WriteHeaderResponse {}))
+    }
+
+    async fn flush(
+        &self,
+        _request: Request<FlushRequest>,
+    ) -> Result<Response<FlushResponse>, Status> {
+        Ok(Response::new(FlushResponse {}))
+    }
+
+    async fn hijack(
+        &self,
+        _request: Request<HijackRequest>,
+    ) -> Result<Response<HijackResponse>, Status> {
+        Err(Status::unimplemented("hijack"))
+    }
+}
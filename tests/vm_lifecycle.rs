@@ -0,0 +1,304 @@
+mod mockhost;
+
+use mockhost::proto::vm_proto::*;
+use mockhost::MockHost;
+use serde_json::json;
+use std::time::Duration;
+
+// https://github.com/ava-labs/avalanchego/blob/master/snow/choices/status.go
+const STATUS_ACCEPTED: u32 = 3;
+
+// https://github.com/ava-labs/avalanchego/blob/master/snow/engine/common/message.go#L13
+const PENDING_TRANSACTIONS: u32 = 0;
+
+// CB58 with a 4 byte SHA256 checksum, as the VM's Encoding::Cb58 produces it
+fn cb58(bytes: &[u8]) -> String {
+    let checksum = hmac_sha256::Hash::hash(bytes);
+    bs58::encode([bytes, &checksum[..4]].concat()).into_string()
+}
+
+// A host with an initialized VM, preferring the genesis block, and the id of the server
+// its JSON-RPC handlers are served on
+async fn start() -> (MockHost, InitializeResponse, u32) {
+    let mut host = MockHost::start().await;
+    let init = host.initialize(b"landslide test genesis").await;
+
+    host.vm
+        .set_preference(SetPreferenceRequest {
+            id: init.last_accepted_id.clone(),
+        })
+        .await
+        .unwrap();
+
+    let handlers = host.vm.create_handlers(()).await.unwrap().into_inner();
+    let handler_server = handlers.handlers[0].server;
+
+    (host, init, handler_server)
+}
+
+// As start, with the chain bootstrapped and in normal operation
+async fn start_bootstrapped() -> (MockHost, InitializeResponse, u32) {
+    let (mut host, init, handler_server) = start().await;
+    host.vm.bootstrapping(()).await.unwrap();
+    host.vm.bootstrapped(()).await.unwrap();
+    (host, init, handler_server)
+}
+
+// Proposes data through the JSON-RPC API, as a user would, and builds a block with it
+// once the VM says it is ready
+async fn propose_and_build(
+    host: &mut MockHost,
+    handler_server: u32,
+    data: &[u8],
+) -> BuildBlockResponse {
+    let reply = host
+        .call_jsonrpc(
+            handler_server,
+            "timestampvm.proposeBlock",
+            json!({ "data": cb58(data) }),
+        )
+        .await;
+    assert_eq!(reply["result"]["success"], true, "reply: {}", reply);
+
+    let message = tokio::time::timeout(Duration::from_secs(10), host.engine_messages.recv())
+        .await
+        .expect("The VM never notified the engine of the proposed block")
+        .unwrap();
+    assert_eq!(message, PENDING_TRANSACTIONS);
+
+    host.vm.build_block(()).await.unwrap().into_inner()
+}
+
+async fn verify_and_accept(host: &mut MockHost, built: &BuildBlockResponse) {
+    host.vm
+        .block_verify(BlockVerifyRequest {
+            bytes: built.bytes.clone(),
+        })
+        .await
+        .unwrap();
+    host.vm
+        .block_accept(BlockAcceptRequest {
+            id: built.id.clone(),
+        })
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let (host, init, _) = start().await;
+
+    assert_eq!(init.last_accepted_id.len(), 32);
+    assert_eq!(init.height, 0);
+    assert!(host.db.get(b"singletonstate_initialized").await.is_some());
+}
+
+// Nothing can be proposed until the chain is bootstrapped
+#[tokio::test]
+async fn test_bootstrapping() {
+    let (mut host, _, handler_server) = start().await;
+
+    host.vm.bootstrapping(()).await.unwrap();
+    let reply = host
+        .call_jsonrpc(handler_server, "timestampvm.lifecycle", json!([]))
+        .await;
+    assert_eq!(
+        reply["result"]["lifecycle"], "Bootstrapping",
+        "reply: {}",
+        reply
+    );
+    let reply = host
+        .call_jsonrpc(
+            handler_server,
+            "timestampvm.proposeBlock",
+            json!({ "data": cb58(&[1u8; 32]) }),
+        )
+        .await;
+    assert!(reply["error"].is_object(), "reply: {}", reply);
+
+    host.vm.bootstrapped(()).await.unwrap();
+    let health = host.vm.health(()).await.unwrap().into_inner();
+    let details: serde_json::Value = serde_json::from_str(&health.details).unwrap();
+    assert_eq!(details["healthy"], true, "details: {}", details);
+    assert_eq!(details["lifecycle"], "NormalOp", "details: {}", details);
+}
+
+#[tokio::test]
+async fn test_proposals_are_gossiped() {
+    let (mut host, _, handler_server) = start_bootstrapped().await;
+
+    let data = [7u8; 32];
+    propose_and_build(&mut host, handler_server, &data).await;
+
+    // [codec version: u16] [data]
+    let gossip = tokio::time::timeout(Duration::from_secs(10), host.gossip_messages.recv())
+        .await
+        .expect("The VM never gossiped the proposed block data")
+        .unwrap();
+    assert_eq!(gossip, [&[0u8, 0][..], &data[..]].concat());
+}
+
+#[tokio::test]
+async fn test_build_verify_accept() {
+    let (mut host, init, handler_server) = start_bootstrapped().await;
+
+    let data = [7u8; 32];
+    let built = propose_and_build(&mut host, handler_server, &data).await;
+    assert_eq!(built.parent_id, init.last_accepted_id);
+    assert_eq!(built.height, 1);
+
+    let parsed = host
+        .vm
+        .parse_block(ParseBlockRequest {
+            bytes: built.bytes.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(parsed.id, built.id);
+
+    verify_and_accept(&mut host, &built).await;
+
+    let accepted = host
+        .vm
+        .get_block(GetBlockRequest {
+            id: built.id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(accepted.status, STATUS_ACCEPTED);
+    assert_eq!(accepted.bytes, built.bytes);
+
+    let reply = host
+        .call_jsonrpc(
+            handler_server,
+            "timestampvm.getBlockByHeight",
+            json!({ "height": 1 }),
+        )
+        .await;
+    assert_eq!(reply["result"]["id"], cb58(&built.id), "reply: {}", reply);
+    assert_eq!(reply["result"]["data"], cb58(&data), "reply: {}", reply);
+}
+
+// The host may repeat a decision, but a decided block never changes its status
+#[tokio::test]
+async fn test_decisions_are_final() {
+    let (mut host, _, handler_server) = start_bootstrapped().await;
+    let built = propose_and_build(&mut host, handler_server, &[7u8; 32]).await;
+    verify_and_accept(&mut host, &built).await;
+
+    host.vm
+        .block_accept(BlockAcceptRequest {
+            id: built.id.clone(),
+        })
+        .await
+        .unwrap();
+    let err = host
+        .vm
+        .block_reject(BlockRejectRequest {
+            id: built.id.clone(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(
+        err.metadata().get("landslide-reason").unwrap(),
+        "BLOCK_ALREADY_DECIDED"
+    );
+
+    // Verifying it again leaves it accepted
+    host.vm
+        .block_verify(BlockVerifyRequest {
+            bytes: built.bytes.clone(),
+        })
+        .await
+        .unwrap();
+    let reverified = host
+        .vm
+        .get_block(GetBlockRequest { id: built.id })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(reverified.status, STATUS_ACCEPTED);
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let (mut host, init, _) = start_bootstrapped().await;
+    let genesis = host
+        .vm
+        .get_block(GetBlockRequest {
+            id: init.last_accepted_id.clone(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    host.vm.shutdown(()).await.unwrap();
+
+    // Later calls fail cleanly, rather than reaching a closed database
+    assert!(host
+        .vm
+        .get_block(GetBlockRequest {
+            id: init.last_accepted_id.clone(),
+        })
+        .await
+        .is_err());
+    assert!(host.vm.health(()).await.is_err());
+
+    // Errors in a batch keep their own code and reason, with the block's index added
+    let err = host
+        .vm
+        .batched_parse_block(BatchedParseBlockRequest {
+            request: vec![genesis.bytes],
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    assert!(err.message().contains("index 0 of 1"), "{}", err.message());
+    assert!(err.metadata().get("landslide-reason").is_some());
+
+    // The host may shut the VM down more than once, and only the first time does anything
+    host.vm.shutdown(()).await.unwrap();
+}
+
+// A question for a peer goes out through the AppSender to a connected peer, and its
+// answer comes back through the VM while the JSON-RPC call waits on it
+#[tokio::test]
+async fn test_peer_has_block() {
+    let (mut host, init, handler_server) = start().await;
+    let ask = json!({ "id": cb58(&init.last_accepted_id) });
+
+    // Nobody to ask yet
+    let reply = host
+        .call_jsonrpc(handler_server, "timestampvm.peerHasBlock", ask.clone())
+        .await;
+    assert!(reply["error"].is_object(), "reply: {}", reply);
+
+    let peer = vec![9u8; 20];
+    host.vm
+        .connected(ConnectedRequest {
+            node_id: peer.clone(),
+            version: "avalanche/1.7.4".to_string(),
+        })
+        .await
+        .unwrap();
+
+    // codec version 0, HasBlockResponse (message type 2), has_block
+    host.answer_app_requests(vec![0, 0, 0, 2, 1]).await;
+
+    let reply = host
+        .call_jsonrpc(handler_server, "timestampvm.peerHasBlock", ask)
+        .await;
+    assert_eq!(reply["result"]["hasBlock"], true, "reply: {}", reply);
+    assert_eq!(
+        reply["result"]["nodeID"],
+        format!("NodeID-{}", cb58(&peer)),
+        "reply: {}",
+        reply
+    );
+
+    let request = host.app_requests.recv().await.unwrap();
+    assert_eq!(request.node_i_ds, vec![peer]);
+}